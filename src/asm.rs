@@ -0,0 +1,630 @@
+use crate::instruction::{Instruction, Operand};
+use crate::trap::TrapRoutine;
+use byteorder::{BigEndian, WriteBytesExt};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io::{self, Write};
+
+/// Error raised while assembling, `line` and `column` are 1-based positions in the source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Result of assembling a source file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assembly {
+    /// Address given by `.ORIG`
+    pub origin: u16,
+    /// Words placed in memory starting at `origin`
+    pub words: Vec<u16>,
+    /// Label name to address
+    pub symbols: BTreeMap<String, u16>,
+    /// Source line (1-based) that produced the word at each address
+    pub line_map: BTreeMap<u16, usize>,
+    /// Addresses holding instructions, as opposed to data placed by directives
+    pub code: BTreeSet<u16>,
+}
+
+impl Assembly {
+    /// Write the object image: the origin followed by the words, all big-endian
+    pub fn write_obj<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_u16::<BigEndian>(self.origin)?;
+        for &word in &self.words {
+            writer.write_u16::<BigEndian>(word)?;
+        }
+        Ok(())
+    }
+
+    /// Write the symbol table in the format produced by the reference `lc3as` assembler
+    pub fn write_sym<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "// Symbol table")?;
+        writeln!(writer, "// Scope level 0:")?;
+        writeln!(writer, "//\tSymbol Name       Page Address")?;
+        writeln!(writer, "//\t----------------  ------------")?;
+        let mut symbols: Vec<_> = self.symbols.iter().collect();
+        symbols.sort_by_key(|(_, &address)| address);
+        for (name, address) in symbols {
+            writeln!(writer, "//\t{:<16}  {:04X}", name, address)?;
+        }
+        writeln!(writer)
+    }
+}
+
+/// A whitespace or comma separated piece of a source line
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    column: usize,
+}
+
+/// A source line reduced to what it places in memory
+#[derive(Debug)]
+struct Statement {
+    line: usize,
+    address: u16,
+    mnemonic: Token,
+    operands: Vec<Token>,
+}
+
+fn error(line: usize, column: usize, message: impl Into<String>) -> AsmError {
+    AsmError {
+        line,
+        column,
+        message: message.into(),
+    }
+}
+
+/// Assemble LC-3 source text into an object image
+pub fn assemble(source: &str) -> Result<Assembly, AsmError> {
+    let mut origin: Option<u16> = None;
+    let mut address: u32 = 0;
+    let mut symbols = BTreeMap::new();
+    let mut statements = Vec::new();
+
+    // First pass: assign an address to every label and statement
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut tokens = tokenize(text, line)?.into_iter();
+        let Some(mut first) = tokens.next() else {
+            continue;
+        };
+        if !is_mnemonic(&first.text) {
+            // Operands try numbers before labels, a label such as `x10` could never be used
+            if parse_literal(&first.text).is_some() {
+                return Err(error(
+                    line,
+                    first.column,
+                    format!("label {} reads as a number", first.text),
+                ));
+            }
+            if !is_label(&first.text) {
+                return Err(error(
+                    line,
+                    first.column,
+                    format!("invalid label {}", first.text),
+                ));
+            }
+            let Some(origin) = origin else {
+                return Err(error(line, first.column, "label before .ORIG"));
+            };
+            if symbols.contains_key(&first.text) {
+                return Err(error(
+                    line,
+                    first.column,
+                    format!("duplicate label {}", first.text),
+                ));
+            }
+            symbols.insert(first.text.clone(), origin.wrapping_add(address as u16));
+            match tokens.next() {
+                Some(token) => first = token,
+                None => continue,
+            }
+        }
+
+        let operands: Vec<Token> = tokens.collect();
+        let directive = first.text.to_ascii_uppercase();
+        match directive.as_str() {
+            ".ORIG" => {
+                if origin.is_some() {
+                    return Err(error(line, first.column, "duplicate .ORIG"));
+                }
+                let [value] = expect_operands::<1>(line, &first, &operands)?;
+                origin = Some(parse_number(line, value, 0, 0xFFFF)? as u16);
+                continue;
+            }
+            ".END" => break,
+            _ => {}
+        }
+        let Some(base) = origin else {
+            return Err(error(line, first.column, "statement before .ORIG"));
+        };
+        let size = match directive.as_str() {
+            ".BLKW" => {
+                let [count] = expect_operands::<1>(line, &first, &operands)?;
+                parse_number(line, count, 1, 0xFFFF)? as u32
+            }
+            ".STRINGZ" => {
+                let [text] = expect_operands::<1>(line, &first, &operands)?;
+                parse_string(line, text)?.len() as u32 + 1
+            }
+            _ => 1,
+        };
+        if base as u32 + address + size > 0x10000 {
+            return Err(error(line, first.column, "program does not fit in memory"));
+        }
+        statements.push(Statement {
+            line,
+            address: base.wrapping_add(address as u16),
+            mnemonic: first,
+            operands,
+        });
+        address += size;
+    }
+
+    let Some(origin) = origin else {
+        return Err(error(1, 1, "missing .ORIG"));
+    };
+
+    // Second pass: encode every statement now that all labels are known
+    let mut words = Vec::with_capacity(address as usize);
+    let mut line_map = BTreeMap::new();
+    let mut code = BTreeSet::new();
+    for statement in &statements {
+        if !statement.mnemonic.text.starts_with('.') {
+            code.insert(statement.address);
+        }
+        let encoded = encode(statement, &symbols)?;
+        for offset in 0..encoded.len() {
+            line_map.insert(
+                statement.address.wrapping_add(offset as u16),
+                statement.line,
+            );
+        }
+        words.extend(encoded);
+    }
+
+    Ok(Assembly {
+        origin,
+        words,
+        symbols,
+        line_map,
+        code,
+    })
+}
+
+/// Split a line into tokens, dropping the comment. String literals are kept as a single token.
+fn tokenize(text: &str, line: usize) -> Result<Vec<Token>, AsmError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c == ';' {
+            break;
+        }
+        if c.is_whitespace() || c == ',' {
+            chars.next();
+            continue;
+        }
+        // Columns count characters, not bytes
+        let column = text[..start].chars().count() + 1;
+        let mut token = String::new();
+        if c == '"' {
+            token.push(c);
+            chars.next();
+            let mut closed = false;
+            while let Some((_, c)) = chars.next() {
+                token.push(c);
+                if c == '\\' {
+                    if let Some((_, escaped)) = chars.next() {
+                        token.push(escaped);
+                    }
+                } else if c == '"' {
+                    closed = true;
+                    break;
+                }
+            }
+            if !closed {
+                return Err(error(line, column, "unterminated string"));
+            }
+        } else {
+            while let Some(&(_, c)) = chars.peek() {
+                if c.is_whitespace() || c == ',' || c == ';' {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+        }
+        tokens.push(Token {
+            text: token,
+            column,
+        });
+    }
+    Ok(tokens)
+}
+
+fn is_mnemonic(text: &str) -> bool {
+    let upper = text.to_ascii_uppercase();
+    if upper.starts_with('.') || trap_alias(&upper).is_some() || branch_flags(&upper).is_some() {
+        return true;
+    }
+    matches!(
+        upper.as_str(),
+        "ADD"
+            | "AND"
+            | "NOT"
+            | "JMP"
+            | "RET"
+            | "JSR"
+            | "JSRR"
+            | "LD"
+            | "LDI"
+            | "LDR"
+            | "LEA"
+            | "ST"
+            | "STI"
+            | "STR"
+            | "RTI"
+            | "TRAP"
+    )
+}
+
+fn is_label(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && parse_register(text).is_none()
+        && parse_literal(text).is_none()
+}
+
+/// Condition codes tested by a `BR` mnemonic, plain `BR` tests all of them
+fn branch_flags(upper: &str) -> Option<u16> {
+    let flags = upper.strip_prefix("BR")?;
+    if flags.is_empty() {
+        return Some(0b111);
+    }
+    let mut nzp = 0;
+    let mut last = 0;
+    for c in flags.chars() {
+        let bit = match c {
+            'N' => 0b100,
+            'Z' => 0b010,
+            'P' => 0b001,
+            _ => return None,
+        };
+        // Flags must be unique and written in n, z, p order
+        if bit & nzp != 0 || (last != 0 && bit > last) {
+            return None;
+        }
+        nzp |= bit;
+        last = bit;
+    }
+    Some(nzp)
+}
+
+fn trap_alias(upper: &str) -> Option<TrapRoutine> {
+    Some(match upper {
+        "GETC" => TrapRoutine::GETC,
+        "OUT" => TrapRoutine::OUT,
+        "PUTS" => TrapRoutine::PUTS,
+        "IN" => TrapRoutine::IN,
+        "PUTSP" => TrapRoutine::PUTSP,
+        "HALT" => TrapRoutine::HALT,
+        _ => return None,
+    })
+}
+
+fn parse_register(text: &str) -> Option<u16> {
+    let digits = text.strip_prefix('R').or_else(|| text.strip_prefix('r'))?;
+    match digits.parse::<u16>() {
+        Ok(register) if digits.len() == 1 && register < 8 => Some(register),
+        _ => None,
+    }
+}
+
+/// Parse an address written as an assembler literal (`x3000`, `#12288`) or in Rust notation
+/// (`0x3000`)
+pub fn parse_address(text: &str) -> Option<u16> {
+    parse_literal(text).and_then(|value| u16::try_from(value).ok())
+}
+
+/// Parse the symbol table written by [`Assembly::write_sym`] or the reference `lc3as`
+pub fn parse_symbols(text: &str) -> BTreeMap<String, u16> {
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.trim_start_matches('/').split_whitespace();
+            let name = fields.next()?;
+            let address = u16::from_str_radix(fields.next()?, 16).ok()?;
+            (fields.next().is_none() && is_label(name)).then(|| (name.to_string(), address))
+        })
+        .collect()
+}
+
+/// Parse a numeric literal: `#-12` or `12` decimal, `x3000` hexadecimal, `b1010` binary
+fn parse_literal(text: &str) -> Option<i64> {
+    let (digits, radix) = if let Some(rest) = text.strip_prefix('#') {
+        (rest, 10)
+    } else if let Some(rest) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        (rest, 16)
+    } else if let Some(rest) = text.strip_prefix('x').or_else(|| text.strip_prefix('X')) {
+        (rest, 16)
+    } else if let Some(rest) = text.strip_prefix('b').or_else(|| text.strip_prefix('B')) {
+        (rest, 2)
+    } else {
+        (text, 10)
+    };
+    let (negative, digits) = match digits.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, digits),
+    };
+    if digits.is_empty() {
+        return None;
+    }
+    let value = i64::from_str_radix(digits, radix).ok()?;
+    Some(if negative { -value } else { value })
+}
+
+fn parse_number(line: usize, token: &Token, min: i64, max: i64) -> Result<i64, AsmError> {
+    let value = parse_literal(&token.text)
+        .ok_or_else(|| error(line, token.column, format!("invalid number {}", token.text)))?;
+    if value < min || value > max {
+        return Err(error(
+            line,
+            token.column,
+            format!("{} is out of range [{}, {}]", token.text, min, max),
+        ));
+    }
+    Ok(value)
+}
+
+fn parse_string(line: usize, token: &Token) -> Result<Vec<u16>, AsmError> {
+    let inner = token
+        .text
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .ok_or_else(|| error(line, token.column, "expected a string literal"))?;
+    let mut words = Vec::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        let c = if c == '\\' {
+            match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some('e') => '\x1b',
+                Some('0') => '\0',
+                Some(c @ ('\\' | '"')) => c,
+                _ => return Err(error(line, token.column, "invalid escape sequence")),
+            }
+        } else {
+            c
+        };
+        if !c.is_ascii() {
+            return Err(error(
+                line,
+                token.column,
+                "only ASCII characters are supported",
+            ));
+        }
+        words.push(c as u16);
+    }
+    Ok(words)
+}
+
+fn expect_operands<'a, const N: usize>(
+    line: usize,
+    mnemonic: &Token,
+    operands: &'a [Token],
+) -> Result<[&'a Token; N], AsmError> {
+    if operands.len() != N {
+        let column = operands
+            .get(N)
+            .map(|token| token.column)
+            .unwrap_or(mnemonic.column);
+        return Err(error(
+            line,
+            column,
+            format!(
+                "{} expects {} operand(s), found {}",
+                mnemonic.text,
+                N,
+                operands.len()
+            ),
+        ));
+    }
+    Ok(std::array::from_fn(|i| &operands[i]))
+}
+
+fn register(line: usize, token: &Token) -> Result<u16, AsmError> {
+    parse_register(&token.text).ok_or_else(|| {
+        error(
+            line,
+            token.column,
+            format!("expected a register, found {}", token.text),
+        )
+    })
+}
+
+/// Signed immediate that must fit in `bits` bits
+fn immediate(line: usize, token: &Token, bits: u32) -> Result<i16, AsmError> {
+    let limit = 1i64 << (bits - 1);
+    Ok(parse_number(line, token, -limit, limit - 1)? as i16)
+}
+
+/// PC-relative operand: a label resolved against the incremented PC, or a literal offset
+fn pc_offset(
+    statement: &Statement,
+    token: &Token,
+    bits: u32,
+    symbols: &BTreeMap<String, u16>,
+) -> Result<i16, AsmError> {
+    let line = statement.line;
+    if parse_literal(&token.text).is_some() {
+        return immediate(line, token, bits);
+    }
+    let target = *symbols.get(&token.text).ok_or_else(|| {
+        error(
+            line,
+            token.column,
+            format!("undefined label {}", token.text),
+        )
+    })?;
+    let offset = target as i64 - (statement.address as i64 + 1);
+    let limit = 1i64 << (bits - 1);
+    if offset < -limit || offset >= limit {
+        return Err(error(
+            line,
+            token.column,
+            format!("label {} is too far away ({} words)", token.text, offset),
+        ));
+    }
+    Ok(offset as i16)
+}
+
+fn encode(statement: &Statement, symbols: &BTreeMap<String, u16>) -> Result<Vec<u16>, AsmError> {
+    let line = statement.line;
+    let mnemonic = &statement.mnemonic;
+    let operands = statement.operands.as_slice();
+    let upper = mnemonic.text.to_ascii_uppercase();
+
+    if let Some(nzp) = branch_flags(&upper) {
+        let [target] = expect_operands::<1>(line, mnemonic, operands)?;
+        let instruction = Instruction::Br {
+            n: nzp & 0b100 != 0,
+            z: nzp & 0b010 != 0,
+            p: nzp & 0b001 != 0,
+            offset: pc_offset(statement, target, 9, symbols)?,
+        };
+        return Ok(vec![instruction.encode()]);
+    }
+    if let Some(trap) = trap_alias(&upper) {
+        expect_operands::<0>(line, mnemonic, operands)?;
+        let instruction = Instruction::Trap {
+            vector: trap.vector() as u8,
+        };
+        return Ok(vec![instruction.encode()]);
+    }
+
+    let instruction = match upper.as_str() {
+        "ADD" | "AND" => {
+            let [dr, sr1, operand] = expect_operands::<3>(line, mnemonic, operands)?;
+            let dr = register(line, dr)?;
+            let sr1 = register(line, sr1)?;
+            let operand = match parse_register(&operand.text) {
+                Some(sr2) => Operand::Reg(sr2),
+                None => Operand::Imm(immediate(line, operand, 5)?),
+            };
+            if upper == "ADD" {
+                Instruction::Add { dr, sr1, operand }
+            } else {
+                Instruction::And { dr, sr1, operand }
+            }
+        }
+        "NOT" => {
+            let [dr, sr] = expect_operands::<2>(line, mnemonic, operands)?;
+            Instruction::Not {
+                dr: register(line, dr)?,
+                sr: register(line, sr)?,
+            }
+        }
+        "JMP" => {
+            let [base] = expect_operands::<1>(line, mnemonic, operands)?;
+            Instruction::Jmp {
+                base: register(line, base)?,
+            }
+        }
+        "RET" => {
+            expect_operands::<0>(line, mnemonic, operands)?;
+            Instruction::Jmp { base: 7 }
+        }
+        "JSR" => {
+            let [target] = expect_operands::<1>(line, mnemonic, operands)?;
+            Instruction::Jsr {
+                offset: pc_offset(statement, target, 11, symbols)?,
+            }
+        }
+        "JSRR" => {
+            let [base] = expect_operands::<1>(line, mnemonic, operands)?;
+            Instruction::Jsrr {
+                base: register(line, base)?,
+            }
+        }
+        "LD" | "LDI" | "LEA" | "ST" | "STI" => {
+            let [r, target] = expect_operands::<2>(line, mnemonic, operands)?;
+            let r = register(line, r)?;
+            let offset = pc_offset(statement, target, 9, symbols)?;
+            match upper.as_str() {
+                "LD" => Instruction::Ld { dr: r, offset },
+                "LDI" => Instruction::Ldi { dr: r, offset },
+                "LEA" => Instruction::Lea { dr: r, offset },
+                "ST" => Instruction::St { sr: r, offset },
+                _ => Instruction::Sti { sr: r, offset },
+            }
+        }
+        "LDR" | "STR" => {
+            let [r, base, offset] = expect_operands::<3>(line, mnemonic, operands)?;
+            let r = register(line, r)?;
+            let base = register(line, base)?;
+            let offset = immediate(line, offset, 6)?;
+            if upper == "LDR" {
+                Instruction::Ldr {
+                    dr: r,
+                    base,
+                    offset,
+                }
+            } else {
+                Instruction::Str {
+                    sr: r,
+                    base,
+                    offset,
+                }
+            }
+        }
+        "RTI" => {
+            expect_operands::<0>(line, mnemonic, operands)?;
+            Instruction::Rti
+        }
+        "TRAP" => {
+            let [vector] = expect_operands::<1>(line, mnemonic, operands)?;
+            Instruction::Trap {
+                vector: parse_number(line, vector, 0, 0xFF)? as u8,
+            }
+        }
+        ".FILL" => {
+            let [value] = expect_operands::<1>(line, mnemonic, operands)?;
+            let word = match symbols.get(&value.text) {
+                Some(&address) => address,
+                None => parse_number(line, value, -0x8000, 0xFFFF)? as u16,
+            };
+            return Ok(vec![word]);
+        }
+        ".BLKW" => {
+            let [count] = expect_operands::<1>(line, mnemonic, operands)?;
+            return Ok(vec![0; parse_number(line, count, 1, 0xFFFF)? as usize]);
+        }
+        ".STRINGZ" => {
+            let [text] = expect_operands::<1>(line, mnemonic, operands)?;
+            let mut words = parse_string(line, text)?;
+            words.push(0);
+            return Ok(words);
+        }
+        _ => {
+            return Err(error(
+                line,
+                mnemonic.column,
+                format!("unknown instruction {}", mnemonic.text),
+            ))
+        }
+    };
+    Ok(vec![instruction.encode()])
+}
@@ -11,7 +11,7 @@ How to calculate the maximum memory?
 
  **/
 pub(crate) const MEMORY_MAX: usize = 1 << 16;
-pub(crate) const CPU_REGISTER_COUNT: usize = 10;
+pub(crate) const CPU_REGISTER_COUNT: usize = 13;
 /// - There are just 16 opcodes in LC-3.
 /// - Each instruction is 16 bits long, with the left 4 bits storing the opcode.
 /// - The rest of the bits are used to store the parameters.
@@ -19,8 +19,9 @@ pub(crate) const CPU_INSTRUCTION_BIT_WIDTH: usize = 16;
 pub(crate) const CPU_OPCODE_BIT_SIZE: usize = 4;
 /// The lower addresses are left empty to leave space for the trap routine code.
 pub(crate) const PROGRAM_COUNTER_START: u16 = 0x3000;
+/// The supervisor stack grows down from the end of system space
+pub(crate) const SUPERVISOR_STACK_START: u16 = 0x3000;
 
 pub(crate) const POSITIVE_BIT: u16 = 0;
 pub(crate) const NEGATIVE_BIT: u16 = 1;
 pub(crate) const IMMEDIATE_MODE: u16 = 1;
-pub(crate) const REGISTER_MODE: u16 = 0;
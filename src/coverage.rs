@@ -0,0 +1,138 @@
+use crate::asm::Assembly;
+use crate::cpu::StepOutcome;
+use crate::instruction::Instruction;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+
+/// Outcomes of a conditional branch
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BranchCount {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+/// Executed addresses and the outcome of every conditional `BR`
+#[derive(Debug, Default)]
+pub struct Coverage {
+    executed: HashMap<u16, u64>,
+    branches: HashMap<u16, BranchCount>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for one executed instruction. A branch counts as taken when PC does not
+    /// continue with the next instruction, so a `BR` with offset 0 is never taken.
+    pub fn record(&mut self, outcome: &StepOutcome) {
+        *self.executed.entry(outcome.pc_before).or_default() += 1;
+        if is_conditional_branch(outcome.instruction) {
+            let branch = self.branches.entry(outcome.pc_before).or_default();
+            if outcome.pc_after == outcome.pc_before.wrapping_add(1) {
+                branch.not_taken += 1;
+            } else {
+                branch.taken += 1;
+            }
+        }
+    }
+
+    /// Times the instruction at `address` was executed
+    pub fn count(&self, address: u16) -> u64 {
+        self.executed.get(&address).copied().unwrap_or(0)
+    }
+
+    /// Outcomes of the conditional branch at `address`, `None` when it never executed
+    pub fn branch(&self, address: u16) -> Option<BranchCount> {
+        self.branches.get(&address).copied()
+    }
+
+    /// Source annotated with execution counts: `-` marks lines without instructions and
+    /// `#####` instructions that never executed. Conditional branches get an extra line with
+    /// their outcomes.
+    pub fn annotate(&self, source: &str, assembly: &Assembly) -> String {
+        let lines = code_lines(assembly);
+        let mut out = String::new();
+        for (index, text) in source.lines().enumerate() {
+            let line = index + 1;
+            let Some(&address) = lines.get(&line) else {
+                let _ = writeln!(out, "{:>9}:{:>5}:{}", "-", line, text);
+                continue;
+            };
+            let count = match self.count(address) {
+                0 => "#####".to_string(),
+                count => count.to_string(),
+            };
+            let _ = writeln!(out, "{:>9}:{:>5}:{}", count, line, text);
+            if is_conditional_branch(word(assembly, address)) {
+                let branch = self.branch(address).unwrap_or_default();
+                let _ = writeln!(
+                    out,
+                    "{:>16}branch x{:04X} taken {}, not taken {}",
+                    "", address, branch.taken, branch.not_taken
+                );
+            }
+        }
+        out
+    }
+
+    /// Coverage of `source_name` in the lcov tracefile format
+    pub fn lcov(&self, source_name: &str, assembly: &Assembly) -> String {
+        let lines = code_lines(assembly);
+        let mut out = String::new();
+        let _ = writeln!(out, "TN:");
+        let _ = writeln!(out, "SF:{}", source_name);
+        let (mut branches_found, mut branches_hit) = (0, 0);
+        for (&line, &address) in &lines {
+            if !is_conditional_branch(word(assembly, address)) {
+                continue;
+            }
+            branches_found += 2;
+            match self.branch(address) {
+                Some(branch) => {
+                    let _ = writeln!(out, "BRDA:{},0,0,{}", line, branch.taken);
+                    let _ = writeln!(out, "BRDA:{},0,1,{}", line, branch.not_taken);
+                    branches_hit += (branch.taken > 0) as usize + (branch.not_taken > 0) as usize;
+                }
+                None => {
+                    let _ = writeln!(out, "BRDA:{},0,0,-", line);
+                    let _ = writeln!(out, "BRDA:{},0,1,-", line);
+                }
+            }
+        }
+        let _ = writeln!(out, "BRF:{}", branches_found);
+        let _ = writeln!(out, "BRH:{}", branches_hit);
+        for (&line, &address) in &lines {
+            let _ = writeln!(out, "DA:{},{}", line, self.count(address));
+        }
+        let _ = writeln!(out, "LF:{}", lines.len());
+        let _ = writeln!(
+            out,
+            "LH:{}",
+            lines
+                .values()
+                .filter(|&&address| self.count(address) > 0)
+                .count()
+        );
+        let _ = writeln!(out, "end_of_record");
+        out
+    }
+}
+
+/// Source line of every instruction, mapped to its address
+fn code_lines(assembly: &Assembly) -> BTreeMap<usize, u16> {
+    assembly
+        .code
+        .iter()
+        .filter_map(|address| Some((*assembly.line_map.get(address)?, *address)))
+        .collect()
+}
+
+fn word(assembly: &Assembly, address: u16) -> u16 {
+    assembly.words[address.wrapping_sub(assembly.origin) as usize]
+}
+
+/// `BR` that tests some but not all condition codes
+fn is_conditional_branch(word: u16) -> bool {
+    matches!(Instruction::decode(word), Instruction::Br { n, z, p, .. } if (n || z || p) && !(n && z && p))
+}
@@ -1,7 +1,22 @@
 use crate::constant;
-use crate::constant::NEGATIVE_BIT;
+use crate::constant::{NEGATIVE_BIT, POSITIVE_BIT};
+use crate::device::{self, Console, Device, DeviceContext, Interrupt, IO_PAGE, KEY_READY};
+use crate::error::VmError;
+use crate::instruction::{Instruction, LC3Instruction, Operand};
 use crate::register::{LC3CPURegister::*, LC3ConditionalFlags::*, MemoryMappedRegister};
-use std::io::Read;
+use crate::trap::TrapRoutine;
+use crate::watch::{WatchHit, WatchKind, Watchpoint};
+
+/// PSR bit set while the processor runs in user mode
+pub(crate) const USER_MODE: u16 = 1 << 15;
+/// PSR bits holding the priority level of the running program
+const PRIORITY: u16 = 0x0700;
+/// PSR bits holding the N/Z/P condition codes
+const CONDITION_CODES: u16 = 0x0007;
+/// System space (0x0000 - 0x2FFF) and the I/O page are off limits in user mode
+const USER_SPACE: std::ops::Range<u16> = 0x3000..0xFE00;
+/// Table of interrupt service routine addresses, indexed by interrupt vector
+const INTERRUPT_VECTOR_TABLE: u16 = 0x0100;
 
 #[derive(Debug)]
 #[allow(non_camel_case_types)]
@@ -9,48 +24,602 @@ pub(crate) struct LC3Cpu {
     /** Registers have a size of 17 bit **/
     pub registers: [u16; constant::CPU_REGISTER_COUNT],
     pub memory: [u16; constant::MEMORY_MAX],
+    /// Memory writes performed by the instruction currently being executed
+    memory_writes: Vec<MemoryWrite>,
+    /// Set once the program executes `HALT`, the machine does not execute instructions anymore
+    pub halted: bool,
+    /// Number of instructions executed since the machine was started
+    pub cycles: u64,
+    /// Error raised by a device while accessing its registers, reported once the instruction
+    /// completes
+    device_error: Option<VmError>,
+    /// Devices handling the I/O page
+    pub bus: Bus,
+    pub watchpoints: Vec<Watchpoint>,
+    /// Watchpoints triggered by the instruction currently being executed
+    watch_hits: Vec<WatchHit>,
+    /// Address of the instruction currently being executed
+    current_pc: u16,
+}
+
+/// A register modified by an instruction, `register` is the index into the register file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegisterWrite {
+    pub register: usize,
+    pub old: u16,
+    pub new: u16,
+}
+
+/// A memory location modified by an instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryWrite {
+    pub address: u16,
+    pub old: u16,
+    pub new: u16,
+}
+
+/// Everything observable about the execution of a single instruction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StepOutcome {
+    /// Cycle count at which the instruction was executed
+    pub cycle: u64,
+    /// Raw instruction word that was fetched
+    pub instruction: u16,
+    pub opcode: LC3Instruction,
+    pub pc_before: u16,
+    pub pc_after: u16,
+    pub registers_written: Vec<RegisterWrite>,
+    pub memory_written: Vec<MemoryWrite>,
+    /// Trap routine invoked by a `TRAP` instruction
+    pub trap: Option<TrapRoutine>,
+    pub halted: bool,
+    /// Watchpoints triggered by the instruction, execution should stop when there are any
+    pub watch_hits: Vec<WatchHit>,
 }
 
 impl Default for LC3Cpu {
     fn default() -> Self {
-        return LC3Cpu {
+        let mut cpu = LC3Cpu {
             registers: [0; constant::CPU_REGISTER_COUNT],
             memory: [0; constant::MEMORY_MAX],
+            memory_writes: Vec::new(),
+            device_error: None,
+            bus: Bus::default(),
+            halted: false,
+            cycles: 0,
+            watchpoints: Vec::new(),
+            watch_hits: Vec::new(),
+            current_pc: 0,
         };
+        // Conditional flag always requires a value, set a zero flag by default. The machine
+        // starts in supervisor mode at priority 0 so programs can reach the devices.
+        cpu.set_condition_codes(ZRO as u16);
+        cpu.registers[SSP as usize] = constant::SUPERVISOR_STACK_START;
+        // Set the PC to starting position => 0x3000 is the default
+        cpu.registers[PC as usize] = constant::PROGRAM_COUNTER_START;
+        cpu
     }
 }
 
+/// Function to sign extend a 16 bit integer
+/// - x: unsigned 16-bit integer
+/// - bit_count: number of significant bits in `x`. How many bits of `x` should be considered when performing the sign extension
+///
+/// e.g. x = 1100100 with the bit_count = 6 => 1 is the right most index in the bit set.
+pub fn sign_extend(mut x: u16, bit_count: i32) -> u16 {
+    // Get the rightmost bit index in the bit set and check if the value of the bit is 1 (negative) or 0 (positive)
+    // - 0xFFFF in hexadecimal = 1111 1111 1111 1111 in roms
+    // - If the sign is negative (0) => do OR operation to bit mask with `bit_count` most significant bits set to 1
+    if ((x >> (bit_count - 1)) & 1) == NEGATIVE_BIT {
+        x |= 0xFFFF << bit_count;
+    }
+    x
+}
+
 impl LC3Cpu {
-    pub fn update_flags(self: &mut Self, register: u16) {
+    pub fn update_flags(&mut self, register: u16) {
         if self.registers[register as usize] == 0 {
-            self.registers[COND as usize] = ZRO as u16;
+            self.set_condition_codes(ZRO as u16);
         } else if self.registers[register as usize] >> 15 == NEGATIVE_BIT {
             // the left-most bit indicates negative (1)
-            self.registers[COND as usize] = NEG as u16;
+            self.set_condition_codes(NEG as u16);
         } else {
-            self.registers[COND as usize] = POS as u16;
+            self.set_condition_codes(POS as u16);
         }
     }
 
-    fn handle_keyboard(&mut self) {
-        let mut buffer = [0; 1];
-        std::io::stdin().read_exact(&mut buffer).unwrap();
-        if buffer[0] != 0 {
-            self.mem_write(MemoryMappedRegister::KBSR as u16, 1 << 15);
-            self.mem_write(MemoryMappedRegister::KBDR as u16, buffer[0] as u16);
-        } else {
-            self.mem_write(MemoryMappedRegister::KBSR as u16, 0)
+    /// Set COND and the N/Z/P bits of PSR
+    fn set_condition_codes(&mut self, flags: u16) {
+        self.registers[COND as usize] = flags;
+        self.registers[PSR as usize] = self.registers[PSR as usize] & !CONDITION_CODES | flags;
+    }
+
+    fn set_psr(&mut self, psr: u16) {
+        self.registers[PSR as usize] = psr;
+        self.registers[COND as usize] = psr & CONDITION_CODES;
+    }
+
+    /// Write a register, keeping COND and the condition codes in PSR in sync
+    pub fn set_register(&mut self, register: usize, value: u16) {
+        match register {
+            r if r == COND as usize => self.set_condition_codes(value & CONDITION_CODES),
+            r if r == PSR as usize => self.set_psr(value),
+            _ => self.registers[register] = value,
         }
     }
 
-    pub fn mem_read(self: &mut Self, address: u16) -> u16 {
-        if address == MemoryMappedRegister::KBSR as u16 {
-            self.handle_keyboard();
+    fn user_mode(&self) -> bool {
+        self.registers[PSR as usize] & USER_MODE != 0
+    }
+
+    fn priority(&self) -> u16 {
+        (self.registers[PSR as usize] & PRIORITY) >> 8
+    }
+
+    /// Fail with an access control violation when user mode code touches system space or
+    /// the I/O page
+    fn check_access(&self, address: u16) -> Result<(), VmError> {
+        if self.user_mode() && !USER_SPACE.contains(&address) {
+            return Err(VmError::AccessViolation(address));
+        }
+        Ok(())
+    }
+
+    /// Memory read performed by an instruction on behalf of the running program
+    fn load(&mut self, address: u16) -> Result<u16, VmError> {
+        self.check_access(address)?;
+        Ok(self.mem_read(address))
+    }
+
+    /// Memory write performed by an instruction on behalf of the running program
+    fn store(&mut self, address: u16, data: u16) -> Result<(), VmError> {
+        self.check_access(address)?;
+        self.mem_write(address, data);
+        Ok(())
+    }
+
+    fn push(&mut self, value: u16) {
+        let sp = self.registers[R6 as usize].wrapping_sub(1);
+        self.registers[R6 as usize] = sp;
+        self.mem_write(sp, value);
+    }
+
+    fn pop(&mut self) -> u16 {
+        let sp = self.registers[R6 as usize];
+        self.registers[R6 as usize] = sp.wrapping_add(1);
+        self.mem_read(sp)
+    }
+
+    /// Enter the service routine of the highest priority interrupt requested by a device when
+    /// it exceeds the priority of the running program. PSR and PC are pushed on the
+    /// supervisor stack so `RTI` can resume the interrupted program.
+    fn service_interrupt(&mut self) {
+        let Some(interrupt) = self.bus.interrupt() else {
+            return;
+        };
+        let priority = u16::from(interrupt.priority.min(7));
+        if priority <= self.priority() {
+            return;
+        }
+        let psr = self.registers[PSR as usize];
+        if self.user_mode() {
+            self.registers[USP as usize] = self.registers[R6 as usize];
+            self.registers[R6 as usize] = self.registers[SSP as usize];
+        }
+        self.push(psr);
+        self.push(self.registers[PC as usize]);
+        self.set_psr(priority << 8 | psr & CONDITION_CODES);
+        let entry = INTERRUPT_VECTOR_TABLE + u16::from(interrupt.vector);
+        self.registers[PC as usize] = self.mem_read(entry);
+    }
+
+    /// Wait for a key and consume it, used by the trap routines that read the keyboard
+    pub fn read_input(&mut self) -> Result<u8, VmError> {
+        while self.read_word(MemoryMappedRegister::KBSR as u16)? & KEY_READY == 0 {
+            self.bus.console.wait_for_input(self.cycles)?;
+        }
+        Ok(self.read_word(MemoryMappedRegister::KBDR as u16)? as u8)
+    }
+
+    /// Read a word from the device handling `address`, or from memory if there is none
+    fn read_word(&mut self, address: u16) -> Result<u16, VmError> {
+        if address == MemoryMappedRegister::PSR as u16 {
+            return Ok(self.registers[PSR as usize]);
+        }
+        let value = self.bus.read(address, self.cycles, &mut self.halted)?;
+        Ok(value.unwrap_or(self.memory[address as usize]))
+    }
+
+    /// Put back a memory word overwritten by an instruction. Device registers and PSR are
+    /// left alone, they are restored with the device and register state.
+    pub(crate) fn restore_word(&mut self, address: u16, value: u16) {
+        if address != MemoryMappedRegister::PSR as u16 && self.bus.peek(address).is_none() {
+            self.memory[address as usize] = value;
+        }
+    }
+
+    /// Value of a memory location or device register without side effects
+    pub fn peek(&self, address: u16) -> u16 {
+        if address == MemoryMappedRegister::PSR as u16 {
+            return self.registers[PSR as usize];
+        }
+        self.bus
+            .peek(address)
+            .unwrap_or(self.memory[address as usize])
+    }
+
+    pub fn mem_read(&mut self, address: u16) -> u16 {
+        let value = self.fetch(address);
+        self.watch(WatchKind::Read, address, value, value);
+        value
+    }
+
+    /// Read memory without triggering watchpoints, used for instruction fetch
+    /// Device errors are reported once the instruction completes.
+    fn fetch(&mut self, address: u16) -> u16 {
+        match self.read_word(address) {
+            Ok(value) => value,
+            Err(e) => {
+                self.device_error.get_or_insert(e);
+                self.peek(address)
+            }
+        }
+    }
+
+    pub fn mem_write(&mut self, address: u16, data: u16) {
+        let old = self.peek(address);
+        self.memory_writes.push(MemoryWrite {
+            address,
+            old,
+            new: data,
+        });
+        if address == MemoryMappedRegister::PSR as u16 {
+            self.set_psr(data);
+            self.watch(WatchKind::Write, address, old, data);
+            return;
+        }
+        match self.bus.write(address, data, self.cycles, &mut self.halted) {
+            Ok(true) => {}
+            Ok(false) => self.memory[address as usize] = data,
+            Err(e) => {
+                self.device_error.get_or_insert(e);
+            }
+        }
+        self.watch(WatchKind::Write, address, old, data);
+    }
+
+    /// Record the watchpoints triggered by an access, `new` is the value read or written
+    fn watch(&mut self, kind: WatchKind, address: u16, old: u16, new: u16) {
+        if self
+            .watchpoints
+            .iter()
+            .any(|watchpoint| watchpoint.matches(kind, address, new))
+        {
+            self.watch_hits.push(WatchHit {
+                pc: self.current_pc,
+                address,
+                kind,
+                old,
+                new,
+            });
+        }
+    }
+
+    /// Fetch, decode and execute a single instruction and report what it changed
+    pub fn step(&mut self) -> Result<StepOutcome, VmError> {
+        if self.halted {
+            return Err(VmError::Halted);
+        }
+        let cycle = self.cycles;
+        let registers_before = self.registers;
+        self.memory_writes.clear();
+        self.watch_hits.clear();
+        // Errors from host accesses between steps are not the program's doing
+        self.device_error = None;
+        self.service_interrupt();
+
+        // Fetch the instruction PC points at, then increment PC so PC-relative
+        // operands are computed from the address of the next instruction
+        let pc_before = self.registers[PC as usize];
+        self.current_pc = pc_before;
+        self.check_access(pc_before)?;
+        let instruction: u16 = self.fetch(pc_before);
+        self.registers[PC as usize] = pc_before.wrapping_add(1);
+        let decoded = Instruction::decode(instruction);
+        let opcode = decoded.opcode();
+        // A device error happened first, it is reported over the fault it may have caused
+        let executed = self.execute(decoded, instruction);
+        let executed = match self.device_error.take() {
+            Some(e) => Err(e),
+            None => executed,
+        };
+        let trap = match executed {
+            Ok(trap) => trap,
+            Err(e) => {
+                // Leave PC at the faulting instruction, as for a fault during fetch
+                self.registers[PC as usize] = pc_before;
+                return Err(e);
+            }
+        };
+        self.bus.tick(self.cycles, &mut self.halted)?;
+        self.cycles += 1;
+
+        let registers_written = registers_before
+            .iter()
+            .zip(self.registers.iter())
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(register, (&old, &new))| RegisterWrite { register, old, new })
+            .collect();
+        Ok(StepOutcome {
+            cycle,
+            instruction,
+            opcode,
+            pc_before,
+            pc_after: self.registers[PC as usize],
+            registers_written,
+            memory_written: std::mem::take(&mut self.memory_writes),
+            trap,
+            halted: self.halted,
+            watch_hits: std::mem::take(&mut self.watch_hits),
+        })
+    }
+
+    /// Value of the second source operand of `ADD` and `AND`
+    fn operand(&self, operand: Operand) -> u16 {
+        match operand {
+            Operand::Reg(sr2) => self.registers[sr2 as usize],
+            Operand::Imm(imm5) => imm5 as u16,
+        }
+    }
+
+    /// Execute a decoded instruction, returns the trap routine if one was invoked
+    fn execute(
+        &mut self,
+        instruction: Instruction,
+        word: u16,
+    ) -> Result<Option<TrapRoutine>, VmError> {
+        // PC has already been incremented, PC-relative offsets are added to it
+        let pc = self.registers[PC as usize];
+        match instruction {
+            Instruction::Add { dr, sr1, operand } => {
+                self.registers[dr as usize] =
+                    self.registers[sr1 as usize].wrapping_add(self.operand(operand));
+                self.update_flags(dr);
+            } /* add  */
+            Instruction::And { dr, sr1, operand } => {
+                self.registers[dr as usize] = self.registers[sr1 as usize] & self.operand(operand);
+                self.update_flags(dr);
+            } /* bitwise and */
+            Instruction::Br { n, z, p, offset } => {
+                // If any of the condition codes tested is set, the program branches to the location
+                // specified by adding the sign-extended pc_offset_9 field to the incremented PC.
+                let cond_flag = (n as u16) << 2 | (z as u16) << 1 | p as u16;
+                if cond_flag & self.registers[COND as usize] != POSITIVE_BIT {
+                    self.registers[PC as usize] = pc.wrapping_add(offset as u16);
+                }
+            } /* branch */
+            Instruction::Jmp { base } => {
+                // The program unconditionally jumps to the location specified by the contents of the base register
+                self.registers[PC as usize] = self.registers[base as usize];
+            } /* jump */
+            Instruction::Jsr { offset } => {
+                self.registers[R7 as usize] = pc;
+                self.registers[PC as usize] = pc.wrapping_add(offset as u16);
+            } /* jump to subroutine */
+            Instruction::Jsrr { base } => {
+                // Read the base register first, `JSRR R7` jumps to the old value of R7
+                let target = self.registers[base as usize];
+                self.registers[R7 as usize] = pc;
+                self.registers[PC as usize] = target;
+            } /* jump to subroutine register */
+            Instruction::Ld { dr, offset } => {
+                self.registers[dr as usize] = self.load(pc.wrapping_add(offset as u16))?;
+                self.update_flags(dr);
+            } /* load */
+            Instruction::Ldi { dr, offset } => {
+                let address = self.load(pc.wrapping_add(offset as u16))?;
+                self.registers[dr as usize] = self.load(address)?;
+                self.update_flags(dr);
+            } /* load indirect */
+            Instruction::Ldr { dr, base, offset } => {
+                let address = self.registers[base as usize].wrapping_add(offset as u16);
+                self.registers[dr as usize] = self.load(address)?;
+                self.update_flags(dr);
+            } /* load register */
+            Instruction::Lea { dr, offset } => {
+                self.registers[dr as usize] = pc.wrapping_add(offset as u16);
+                self.update_flags(dr);
+            } /* load effective address */
+            Instruction::Not { dr, sr } => {
+                self.registers[dr as usize] = !self.registers[sr as usize];
+                self.update_flags(dr);
+            } /* bitwise not */
+            Instruction::St { sr, offset } => {
+                self.store(pc.wrapping_add(offset as u16), self.registers[sr as usize])?;
+            } /* store */
+            Instruction::Sti { sr, offset } => {
+                let address = self.load(pc.wrapping_add(offset as u16))?;
+                self.store(address, self.registers[sr as usize])?;
+            } /* store indirect */
+            Instruction::Str { sr, base, offset } => {
+                let address = self.registers[base as usize].wrapping_add(offset as u16);
+                self.store(address, self.registers[sr as usize])?;
+            } /* store register */
+            Instruction::Rti => {
+                if self.user_mode() {
+                    return Err(VmError::PrivilegeViolation(word));
+                }
+                // Resume the interrupted program, switching back to the user stack when it
+                // ran in user mode
+                let resume = self.pop();
+                let psr = self.pop();
+                self.registers[PC as usize] = resume;
+                self.set_psr(psr);
+                if self.user_mode() {
+                    self.registers[SSP as usize] = self.registers[R6 as usize];
+                    self.registers[R6 as usize] = self.registers[USP as usize];
+                }
+            } /* return from interrupt */
+            Instruction::Reserved(_) => {
+                return Err(VmError::IllegalOpcode(word));
+            }
+            Instruction::Trap { vector } => {
+                return Ok(Some(TrapRoutine::execute(self, vector as u16)?));
+            } /* execute trap */
+        }
+        Ok(None)
+    }
+}
+
+/// Routes accesses to the I/O page (0xFE00 - 0xFFFF) to the devices handling them, addresses
+/// no device handles are plain memory
+#[derive(Debug)]
+pub(crate) struct Bus {
+    devices: Vec<Box<dyn Device>>,
+    /// Console shared by the devices and the trap routines
+    pub console: Console,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Bus {
+            devices: device::builtin_devices(),
+            console: Console::default(),
+        }
+    }
+}
+
+impl Bus {
+    /// Plug in a device, its addresses must lie in the I/O page and not be handled already
+    pub fn attach(&mut self, device: Box<dyn Device>) -> Result<(), VmError> {
+        let range = device.range();
+        if range.is_empty() || !IO_PAGE.contains(range.start()) || !IO_PAGE.contains(range.end()) {
+            return Err(VmError::DeviceConflict(*range.start()));
+        }
+        // PSR is a processor register, not a device
+        let psr = MemoryMappedRegister::PSR as u16;
+        if range.contains(&psr) {
+            return Err(VmError::DeviceConflict(psr));
+        }
+        if let Some(other) = self.devices.iter().find(|other| {
+            other.range().start() <= range.end() && range.start() <= other.range().end()
+        }) {
+            let start = *range.start().max(other.range().start());
+            return Err(VmError::DeviceConflict(start));
+        }
+        self.devices.push(device);
+        Ok(())
+    }
+
+    /// State of every device, in the order they were attached
+    pub fn save_states(&self) -> Vec<Vec<u8>> {
+        self.devices
+            .iter()
+            .map(|device| device.save_state())
+            .collect()
+    }
+
+    /// Restore states produced by [`Bus::save_states`]. When a device rejects its state the
+    /// devices are put back the way they were, none is left half restored.
+    pub fn load_states(&mut self, states: &[Vec<u8>]) -> Result<(), VmError> {
+        if states.len() != self.devices.len() {
+            return Err(VmError::InvalidSnapshot(format!(
+                "state of {} devices instead of {}",
+                states.len(),
+                self.devices.len()
+            )));
+        }
+        let saved = self.save_states();
+        let result = self
+            .devices
+            .iter_mut()
+            .zip(states)
+            .try_for_each(|(device, state)| device.load_state(state));
+        if result.is_err() {
+            for (device, state) in self.devices.iter_mut().zip(&saved) {
+                device
+                    .load_state(state)
+                    .expect("devices accept the state they saved");
+            }
+        }
+        result
+    }
+
+    fn device(&self, address: u16) -> Option<usize> {
+        if !IO_PAGE.contains(&address) {
+            return None;
+        }
+        self.devices
+            .iter()
+            .position(|device| device.range().contains(&address))
+    }
+
+    /// Read a device register, `None` when no device handles `address`
+    pub fn read(
+        &mut self,
+        address: u16,
+        cycle: u64,
+        halted: &mut bool,
+    ) -> Result<Option<u16>, VmError> {
+        let Some(index) = self.device(address) else {
+            return Ok(None);
+        };
+        let mut context = DeviceContext {
+            cycle,
+            console: &mut self.console,
+            halted,
+        };
+        self.devices[index].read(address, &mut context).map(Some)
+    }
+
+    /// Write a device register, returns whether a device handles `address`
+    pub fn write(
+        &mut self,
+        address: u16,
+        value: u16,
+        cycle: u64,
+        halted: &mut bool,
+    ) -> Result<bool, VmError> {
+        let Some(index) = self.device(address) else {
+            return Ok(false);
+        };
+        let mut context = DeviceContext {
+            cycle,
+            console: &mut self.console,
+            halted,
+        };
+        self.devices[index].write(address, value, &mut context)?;
+        Ok(true)
+    }
+
+    pub fn peek(&self, address: u16) -> Option<u16> {
+        self.device(address)
+            .map(|index| self.devices[index].peek(address))
+    }
+
+    /// Let every device advance by one instruction
+    pub fn tick(&mut self, cycle: u64, halted: &mut bool) -> Result<(), VmError> {
+        for device in &mut self.devices {
+            let mut context = DeviceContext {
+                cycle,
+                console: &mut self.console,
+                halted: &mut *halted,
+            };
+            device.tick(&mut context)?;
         }
-        self.memory[address as usize]
+        Ok(())
     }
 
-    pub fn mem_write(self: &mut Self, address: u16, data: u16) {
-        self.memory[address as usize] = data;
+    /// Highest priority interrupt requested by a device, the earliest attached device wins
+    /// between equal priorities
+    pub fn interrupt(&self) -> Option<Interrupt> {
+        self.devices
+            .iter()
+            .filter_map(|device| device.interrupt())
+            .fold(None, |best: Option<Interrupt>, interrupt| match best {
+                Some(best) if best.priority >= interrupt.priority => Some(best),
+                _ => Some(interrupt),
+            })
     }
 }
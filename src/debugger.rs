@@ -0,0 +1,477 @@
+use crate::asm::parse_address;
+use crate::constant;
+use crate::cpu::USER_MODE;
+use crate::disasm::{self, Labels};
+use crate::error::VmError;
+use crate::register::condition_codes as flags;
+use crate::register::LC3CPURegister::{self, COND, PC, PSR};
+use crate::vm::Vm;
+use crate::watch::{WatchHit, WatchKind, Watchpoint};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::io::{self, BufRead, Write};
+
+/// Number of instructions the debugger can step back over
+const JOURNAL_CAPACITY: usize = 100_000;
+
+const HELP: &str = "\
+step [n]              execute n instructions (default 1)
+continue              run until a breakpoint, HALT or a fault
+reverse-step [n]      undo the last n instructions (default 1)
+reverse-continue      undo instructions back to the previous breakpoint
+last-write <addr>     show the instruction that last wrote the address
+break <addr|label>    stop before executing the instruction at the address
+delete [addr|label]   remove a breakpoint, or all of them
+regs                  show the registers
+mem <addr> [len]      dump len words of memory (default 8)
+set reg <reg> <value> change a register (R0 - R7, PC, COND, PSR, USP, SSP)
+set mem <addr> <value> change a memory location
+watch <addr> [len] [== value]   stop after a write to the range
+rwatch <addr> [len] [== value]  stop after a read from the range
+awatch <addr> [len] [== value]  stop after a read or write
+watch                 list the watchpoints
+unwatch [n]           remove watchpoint n, or all of them
+save <file>           write a snapshot of the machine
+restore <file>        restore the machine from a snapshot
+disasm [addr] [n]     disassemble n instructions (default 8) from addr (default PC)
+where                 show the next instruction
+help                  show this message
+quit                  leave the debugger";
+
+/// Interactive debugger driving a [`Vm`] one instruction at a time.
+/// Addresses can be written as `x3000`, `#12288` or as a label from the symbol table.
+#[derive(Debug)]
+pub struct Debugger {
+    vm: Vm,
+    breakpoints: BTreeSet<u16>,
+    symbols: BTreeMap<String, u16>,
+    labels: Labels,
+}
+
+impl Debugger {
+    pub fn new(mut vm: Vm) -> Self {
+        vm.enable_journal(JOURNAL_CAPACITY);
+        Debugger {
+            vm,
+            breakpoints: BTreeSet::new(),
+            symbols: BTreeMap::new(),
+            labels: Labels::new(),
+        }
+    }
+
+    /// Use `symbols` to resolve labels in commands and to annotate the disassembly
+    pub fn set_symbols(&mut self, symbols: BTreeMap<String, u16>) {
+        self.labels = symbols
+            .iter()
+            .map(|(name, &address)| (address, name.clone()))
+            .collect();
+        self.symbols = symbols;
+    }
+
+    pub fn vm(&self) -> &Vm {
+        &self.vm
+    }
+
+    pub fn vm_mut(&mut self) -> &mut Vm {
+        &mut self.vm
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<u16> {
+        &self.breakpoints
+    }
+
+    /// Read commands from `input` until it is exhausted or `quit` is entered
+    pub fn repl<R: BufRead, W: Write>(&mut self, mut input: R, mut output: W) -> io::Result<()> {
+        writeln!(output, "{}", self.location(self.vm.register(PC)))?;
+        let mut line = String::new();
+        loop {
+            write!(output, "(lc3) ")?;
+            output.flush()?;
+            line.clear();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let command = line.trim();
+            if matches!(command, "quit" | "q") {
+                return Ok(());
+            }
+            if command.is_empty() {
+                continue;
+            }
+            match self.execute(command) {
+                Ok(text) => write!(output, "{}", text)?,
+                Err(message) => writeln!(output, "error: {}", message)?,
+            }
+        }
+    }
+
+    /// Execute a single command and return the text to show
+    pub fn execute(&mut self, command: &str) -> Result<String, String> {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        match words.as_slice() {
+            ["step" | "s"] => self.step(1),
+            ["step" | "s", count] => self.step(parse_count(count)?),
+            ["continue" | "c"] => self.resume(),
+            ["reverse-step" | "rs"] => self.step_back(1),
+            ["reverse-step" | "rs", count] => self.step_back(parse_count(count)?),
+            ["reverse-continue" | "rc"] => self.resume_back(),
+            ["last-write", address] => Ok(self.last_write(self.address(address)?)),
+            ["break" | "b", address] => {
+                let address = self.address(address)?;
+                self.breakpoints.insert(address);
+                Ok(format!("breakpoint at {}\n", self.describe(address)))
+            }
+            ["delete" | "d"] => {
+                self.breakpoints.clear();
+                Ok("deleted all breakpoints\n".to_string())
+            }
+            ["delete" | "d", address] => {
+                let address = self.address(address)?;
+                if self.breakpoints.remove(&address) {
+                    Ok(format!(
+                        "deleted breakpoint at {}\n",
+                        self.describe(address)
+                    ))
+                } else {
+                    Err(format!("no breakpoint at {}", self.describe(address)))
+                }
+            }
+            ["watch"] => Ok(self.list_watchpoints()),
+            ["watch", args @ ..] => self.watch(WatchKind::Write, args),
+            ["rwatch", args @ ..] => self.watch(WatchKind::Read, args),
+            ["awatch", args @ ..] => self.watch(WatchKind::Access, args),
+            ["unwatch"] => {
+                self.vm.clear_watchpoints();
+                Ok("deleted all watchpoints\n".to_string())
+            }
+            ["unwatch", index] => {
+                let index = parse_count(index)?;
+                match self.vm.remove_watchpoint(index) {
+                    Some(_) => Ok(format!("deleted watchpoint {}\n", index)),
+                    None => Err(format!("no watchpoint {}", index)),
+                }
+            }
+            ["regs" | "r"] => Ok(self.registers()),
+            ["mem" | "m", address] => Ok(self.dump(self.address(address)?, 8)),
+            ["mem" | "m", address, len] => Ok(self.dump(self.address(address)?, parse_count(len)?)),
+            ["set", "reg", register, value] => {
+                let register = LC3CPURegister::from_name(register)
+                    .ok_or_else(|| format!("unknown register {}", register))?;
+                let value = self.value(value)?;
+                self.vm.set_register(register, value);
+                Ok(format!("{:?} = x{:04X}\n", register, value))
+            }
+            ["set", "mem", address, value] => {
+                let address = self.address(address)?;
+                let value = self.value(value)?;
+                self.vm.set_memory(address, value);
+                Ok(format!("x{:04X} = x{:04X}\n", address, value))
+            }
+            ["disasm"] => Ok(self.disassemble(self.vm.register(PC), 8)),
+            ["disasm", address] => Ok(self.disassemble(self.address(address)?, 8)),
+            ["disasm", address, count] => {
+                Ok(self.disassemble(self.address(address)?, parse_count(count)?))
+            }
+            ["save", path] => {
+                self.vm.save_state(path).map_err(|e| e.to_string())?;
+                Ok(format!("saved state to {}\n", path))
+            }
+            ["restore", path] => {
+                self.vm.load_state(path).map_err(|e| e.to_string())?;
+                Ok(format!("{}\n", self.location(self.vm.register(PC))))
+            }
+            ["where" | "w"] => Ok(format!("{}\n", self.location(self.vm.register(PC)))),
+            ["help" | "h"] => Ok(format!("{}\n", HELP)),
+            _ => Err(format!("unknown command `{}`, try `help`", command)),
+        }
+    }
+
+    /// Execute up to `count` instructions, stopping early on HALT, a watchpoint or a fault
+    fn step(&mut self, count: usize) -> Result<String, String> {
+        for _ in 0..count {
+            let outcome = self.vm.step().map_err(|e| self.fault(e))?;
+            if outcome.halted {
+                return Ok("program halted\n".to_string());
+            }
+            if !outcome.watch_hits.is_empty() {
+                return Ok(self.watch_hits(&outcome.watch_hits));
+            }
+        }
+        Ok(format!("{}\n", self.location(self.vm.register(PC))))
+    }
+
+    /// Run until the next breakpoint. The instruction at the current PC always executes so
+    /// continuing from a breakpoint makes progress.
+    fn resume(&mut self) -> Result<String, String> {
+        loop {
+            let outcome = self.vm.step().map_err(|e| self.fault(e))?;
+            if outcome.halted {
+                return Ok("program halted\n".to_string());
+            }
+            if !outcome.watch_hits.is_empty() {
+                return Ok(self.watch_hits(&outcome.watch_hits));
+            }
+            let pc = self.vm.register(PC);
+            if self.breakpoints.contains(&pc) {
+                return Ok(format!("breakpoint hit\n{}\n", self.location(pc)));
+            }
+        }
+    }
+
+    /// Undo up to `count` instructions, stopping early when the journal is exhausted
+    fn step_back(&mut self, count: usize) -> Result<String, String> {
+        for _ in 0..count {
+            if self.vm.step_back().is_none() {
+                return Ok(format!(
+                    "reached the start of the journal\n{}\n",
+                    self.location(self.vm.register(PC))
+                ));
+            }
+        }
+        Ok(format!("{}\n", self.location(self.vm.register(PC))))
+    }
+
+    /// Undo instructions until PC reaches a breakpoint. The instruction before the current PC
+    /// is always undone so reversing from a breakpoint makes progress.
+    fn resume_back(&mut self) -> Result<String, String> {
+        loop {
+            if self.vm.step_back().is_none() {
+                return Ok(format!(
+                    "reached the start of the journal\n{}\n",
+                    self.location(self.vm.register(PC))
+                ));
+            }
+            let pc = self.vm.register(PC);
+            if self.breakpoints.contains(&pc) {
+                return Ok(format!("breakpoint hit\n{}\n", self.location(pc)));
+            }
+        }
+    }
+
+    /// `last-write <addr>`
+    fn last_write(&self, address: u16) -> String {
+        let journal = self
+            .vm
+            .journal()
+            .expect("the debugger always records a journal");
+        match journal.last_write(address) {
+            Some(outcome) => {
+                let write = outcome
+                    .memory_written
+                    .iter()
+                    .rev()
+                    .find(|write| write.address == address)
+                    .expect("journal entry wrote the address");
+                format!(
+                    "{} written at cycle {}: x{:04X} -> x{:04X}\n{}\n",
+                    self.describe(address),
+                    outcome.cycle,
+                    write.old,
+                    write.new,
+                    self.location(outcome.pc_before)
+                )
+            }
+            None => format!(
+                "no write to {} in the last {} instructions\n",
+                self.describe(address),
+                journal.len()
+            ),
+        }
+    }
+
+    /// `watch <addr> [len] [== value]`
+    fn watch(&mut self, kind: WatchKind, args: &[&str]) -> Result<String, String> {
+        let (range, condition) = match args {
+            [range @ .., "==", value] => (range, Some(self.value(value)?)),
+            _ => (args, None),
+        };
+        let (start, len) = match range {
+            [address] => (self.address(address)?, 1),
+            [address, len] => (self.address(address)?, parse_count(len)?),
+            _ => return Err("usage: watch <addr> [len] [== value]".to_string()),
+        };
+        if len == 0 || len > constant::MEMORY_MAX - start as usize {
+            return Err(format!("invalid length {}", len));
+        }
+        let mut watchpoint = Watchpoint::range(start, start + (len - 1) as u16, kind);
+        if let Some(value) = condition {
+            watchpoint = watchpoint.with_value(value);
+        }
+        let index = self.vm.add_watchpoint(watchpoint);
+        Ok(format!(
+            "watchpoint {}: {}\n",
+            index,
+            self.describe_watchpoint(&watchpoint)
+        ))
+    }
+
+    fn list_watchpoints(&self) -> String {
+        let mut out = String::new();
+        for (index, watchpoint) in self.vm.watchpoints().iter().enumerate() {
+            let _ = writeln!(out, "{}: {}", index, self.describe_watchpoint(watchpoint));
+        }
+        if out.is_empty() {
+            out.push_str("no watchpoints\n");
+        }
+        out
+    }
+
+    fn describe_watchpoint(&self, watchpoint: &Watchpoint) -> String {
+        let kind = match watchpoint.kind {
+            WatchKind::Read => "read",
+            WatchKind::Write => "write",
+            WatchKind::Access => "access",
+        };
+        let mut text = format!("{} {}", kind, self.describe(watchpoint.start));
+        if watchpoint.end != watchpoint.start {
+            let _ = write!(text, " - {}", self.describe(watchpoint.end));
+        }
+        if let Some(value) = watchpoint.value {
+            let _ = write!(text, " == x{:04X}", value);
+        }
+        text
+    }
+
+    fn watch_hits(&self, hits: &[WatchHit]) -> String {
+        let mut out = String::new();
+        for hit in hits {
+            let _ = match hit.kind {
+                WatchKind::Write => writeln!(
+                    out,
+                    "watchpoint: write {} by x{:04X}: x{:04X} -> x{:04X}",
+                    self.describe(hit.address),
+                    hit.pc,
+                    hit.old,
+                    hit.new
+                ),
+                _ => writeln!(
+                    out,
+                    "watchpoint: read {} by x{:04X}: x{:04X}",
+                    self.describe(hit.address),
+                    hit.pc,
+                    hit.new
+                ),
+            };
+        }
+        let _ = writeln!(out, "{}", self.location(self.vm.register(PC)));
+        out
+    }
+
+    fn fault(&self, e: VmError) -> String {
+        format!("{} at {}", e, self.describe(self.vm.register(PC)))
+    }
+
+    /// Resolve a label or an address literal
+    fn address(&self, text: &str) -> Result<u16, String> {
+        self.symbols
+            .get(text)
+            .copied()
+            .or_else(|| parse_address(text))
+            .ok_or_else(|| format!("unknown address or label {}", text))
+    }
+
+    /// A value for `set`, negative decimal literals are stored in two's complement
+    fn value(&self, text: &str) -> Result<u16, String> {
+        if let Some(negative) = text.strip_prefix("#-") {
+            if let Ok(value) = negative.parse::<u16>() {
+                if value <= 0x8000 {
+                    return Ok(value.wrapping_neg());
+                }
+            }
+        }
+        self.address(text)
+    }
+
+    /// `x3005 <LOOP+1>` style description of an address
+    fn describe(&self, address: u16) -> String {
+        match self.labels.range(..=address).next_back() {
+            Some((&base, name)) if base == address => format!("x{:04X} <{}>", address, name),
+            Some((&base, name)) => format!("x{:04X} <{}+{}>", address, name, address - base),
+            None => format!("x{:04X}", address),
+        }
+    }
+
+    fn location(&self, address: u16) -> String {
+        let word = self.vm.memory(address);
+        format!(
+            "{}: x{:04X}  {}",
+            self.describe(address),
+            word,
+            disasm::disassemble_at(word, address, &self.labels)
+        )
+    }
+
+    fn registers(&self) -> String {
+        let mut out = String::new();
+        for register in LC3CPURegister::ALL {
+            let value = self.vm.register(register);
+            let _ = match register {
+                COND => writeln!(out, "{:<4} {}", "COND", flags(value)),
+                PSR => writeln!(
+                    out,
+                    "{:<4} x{:04X}  {} mode, priority {}",
+                    "PSR",
+                    value,
+                    if value & USER_MODE != 0 {
+                        "user"
+                    } else {
+                        "supervisor"
+                    },
+                    (value >> 8) & 0x7
+                ),
+                _ => writeln!(
+                    out,
+                    "{:<4} x{:04X}  #{}",
+                    format!("{:?}", register),
+                    value,
+                    value as i16
+                ),
+            };
+        }
+        out
+    }
+
+    /// `len` words from `address`, at most the whole address space once
+    fn dump(&self, address: u16, len: usize) -> String {
+        let len = len.min(constant::MEMORY_MAX);
+        let mut out = String::new();
+        for row in (0..len).step_by(8) {
+            let start = address.wrapping_add(row as u16);
+            let _ = write!(out, "x{:04X}:", start);
+            for offset in row..len.min(row.saturating_add(8)) {
+                let _ = write!(
+                    out,
+                    " x{:04X}",
+                    self.vm.memory(address.wrapping_add(offset as u16))
+                );
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// `count` instructions from `address`, at most the whole address space once
+    fn disassemble(&self, address: u16, count: usize) -> String {
+        let mut out = String::new();
+        for offset in 0..count.min(constant::MEMORY_MAX) {
+            let current = address.wrapping_add(offset as u16);
+            let marker = if current == self.vm.register(PC) {
+                "=>"
+            } else {
+                "  "
+            };
+            let stop = if self.breakpoints.contains(&current) {
+                "*"
+            } else {
+                " "
+            };
+            let _ = writeln!(out, "{}{}{}", marker, stop, self.location(current));
+        }
+        out
+    }
+}
+
+fn parse_count(text: &str) -> Result<usize, String> {
+    text.parse::<usize>()
+        .map_err(|_| format!("invalid count {}", text))
+}
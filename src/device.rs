@@ -0,0 +1,440 @@
+use crate::error::VmError;
+use crate::input::InputEvent;
+use crate::io::{IoBackend, Terminal};
+use crate::register::MemoryMappedRegister::{DDR, DSR, KBDR, KBSR, MCR};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::ops::RangeInclusive;
+
+/// Addresses reserved for memory-mapped devices
+pub const IO_PAGE: RangeInclusive<u16> = 0xFE00..=0xFFFF;
+
+/// KBSR bit telling that a key is waiting in KBDR
+pub(crate) const KEY_READY: u16 = 1 << 15;
+/// KBSR bit asking the keyboard to interrupt the program when a key arrives
+const KEY_INTERRUPT_ENABLE: u16 = 1 << 14;
+/// DSR bit telling that the display accepts a character in DDR
+const DISPLAY_READY: u16 = 1 << 15;
+/// MCR bit enabling the clock, the machine stops once it is cleared
+pub(crate) const CLOCK_ENABLE: u16 = 1 << 15;
+
+/// Interrupt requested by a device
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Interrupt {
+    /// Entry of the interrupt vector table (x0100 + vector) holding the service routine
+    pub vector: u8,
+    /// Priority level 0 - 7, the interrupt is taken when it exceeds the running priority
+    pub priority: u8,
+}
+
+/// A peripheral plugged into the I/O page. Reads and writes of its addresses are handed to
+/// the device instead of memory.
+pub trait Device: Send {
+    /// Addresses handled by the device, inside [`IO_PAGE`]
+    fn range(&self) -> RangeInclusive<u16>;
+
+    /// A program reads a device register, reads may have side effects
+    fn read(&mut self, address: u16, context: &mut DeviceContext) -> Result<u16, VmError>;
+
+    /// A program writes a device register
+    fn write(
+        &mut self,
+        address: u16,
+        value: u16,
+        context: &mut DeviceContext,
+    ) -> Result<(), VmError>;
+
+    /// Value of a register without side effects, used by debuggers
+    fn peek(&self, address: u16) -> u16;
+
+    /// Called once per executed instruction
+    fn tick(&mut self, _context: &mut DeviceContext) -> Result<(), VmError> {
+        Ok(())
+    }
+
+    /// Interrupt the device is currently requesting
+    fn interrupt(&self) -> Option<Interrupt> {
+        None
+    }
+
+    /// Internal state saved in snapshots
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Restore state produced by [`Device::save_state`]
+    fn load_state(&mut self, _state: &[u8]) -> Result<(), VmError> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for dyn Device {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Device({:#06x}..={:#06x})",
+            self.range().start(),
+            self.range().end()
+        )
+    }
+}
+
+/// What a device can reach while handling an access
+pub struct DeviceContext<'a> {
+    pub(crate) cycle: u64,
+    pub(crate) console: &'a mut Console,
+    pub(crate) halted: &'a mut bool,
+}
+
+impl DeviceContext<'_> {
+    /// Cycle of the instruction being executed
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    /// Next console byte if one is available, never blocks
+    pub fn read_input(&mut self) -> Result<Option<u8>, VmError> {
+        self.console.take_input(self.cycle)
+    }
+
+    /// Whether a console byte is available, without taking it. Never blocks.
+    pub fn input_ready(&mut self) -> Result<bool, VmError> {
+        self.console.poll_input(self.cycle)
+    }
+
+    pub fn write_output(&mut self, byte: u8) -> Result<(), VmError> {
+        self.console.write_byte(byte)?;
+        Ok(self.console.flush()?)
+    }
+
+    /// Stop the machine after the current instruction
+    pub fn stop_clock(&mut self) {
+        *self.halted = true;
+    }
+}
+
+/// The console shared by the devices and the trap routines. Every input byte goes through
+/// here so it can be recorded, or taken from a replayed log instead of the backend.
+#[derive(Debug)]
+pub(crate) struct Console {
+    pub io: Box<dyn IoBackend>,
+    /// Byte read by a blocking wait that no device has taken yet
+    pending: Option<u8>,
+    /// Cycle a poll first found the next input byte at, the byte is recorded with it
+    seen: Option<u64>,
+    /// Every input byte taken so far, kept while recording
+    pub recorded: Option<Vec<InputEvent>>,
+    /// Input bytes fed to the program instead of reading the backend
+    pub replay: Option<VecDeque<InputEvent>>,
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Console {
+            io: Box::new(Terminal::new()),
+            pending: None,
+            seen: None,
+            recorded: None,
+            replay: None,
+        }
+    }
+}
+
+impl Console {
+    /// Whether an input byte is available at `cycle`, the byte is left for
+    /// [`Console::take_input`]
+    pub fn poll_input(&mut self, cycle: u64) -> Result<bool, VmError> {
+        let ready = if self.pending.is_some() {
+            true
+        } else if self.replay.is_some() {
+            self.replayed(cycle)?.is_some()
+        } else {
+            self.io.poll()?
+        };
+        if ready {
+            self.seen.get_or_insert(cycle);
+        }
+        Ok(ready)
+    }
+
+    /// Take the next input byte available at `cycle`. Once a replayed log is exhausted no
+    /// more keys arrive, just like the recorded run saw none.
+    pub fn take_input(&mut self, cycle: u64) -> Result<Option<u8>, VmError> {
+        let byte = match self.pending.take() {
+            Some(byte) => byte,
+            None if self.replay.is_some() => match self.replayed(cycle)? {
+                Some(event) => {
+                    if let Some(replay) = &mut self.replay {
+                        replay.pop_front();
+                    }
+                    event.byte
+                }
+                None => return Ok(None),
+            },
+            None => match self.io.read_byte()? {
+                Some(byte) => byte,
+                None => return Ok(None),
+            },
+        };
+        // Logged with the cycle the program first saw it at, that is when KBSR turned ready
+        let cycle = self.seen.take().unwrap_or(cycle);
+        if let Some(recorded) = &mut self.recorded {
+            recorded.push(InputEvent { cycle, byte });
+        }
+        Ok(Some(byte))
+    }
+
+    /// Block until an input byte is available at `cycle`. When replaying, the log must have
+    /// a byte for this cycle.
+    pub fn wait_for_input(&mut self, cycle: u64) -> Result<(), VmError> {
+        if self.pending.is_some() {
+            return Ok(());
+        }
+        match &self.replay {
+            Some(replay) => {
+                let event = *replay.front().ok_or_else(input_log_exhausted)?;
+                if self.replayed(cycle)?.is_none() {
+                    return Err(VmError::ReplayDiverged {
+                        cycle,
+                        expected: event.cycle,
+                    });
+                }
+            }
+            None => self.pending = Some(self.io.read_byte_blocking()?),
+        }
+        Ok(())
+    }
+
+    /// The next replayed byte if it is due at `cycle`. A byte logged for an earlier cycle
+    /// that no poll saw then means the program took a different path than recorded.
+    fn replayed(&self, cycle: u64) -> Result<Option<InputEvent>, VmError> {
+        let Some(&event) = self.replay.as_ref().and_then(VecDeque::front) else {
+            return Ok(None);
+        };
+        if event.cycle > cycle {
+            return Ok(None);
+        }
+        if event.cycle < cycle && self.seen.is_none() {
+            return Err(VmError::ReplayDiverged {
+                cycle,
+                expected: event.cycle,
+            });
+        }
+        Ok(Some(event))
+    }
+
+    pub fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        self.io.write_byte(byte)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.io.flush()
+    }
+}
+
+fn input_log_exhausted() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "input log exhausted")
+}
+
+/// Keyboard status (KBSR) and data (KBDR) registers.
+/// KBSR bit 15 tells whether a key is waiting on the console and polling it never blocks nor
+/// takes the key, reading KBDR does. Setting KBSR bit 14 requests an interrupt whenever a key
+/// is waiting.
+#[derive(Debug, Default)]
+pub(crate) struct Keyboard {
+    /// A key was waiting when the console was last polled
+    ready: bool,
+    /// KBDR keeps the last key read
+    data: u16,
+    interrupt_enable: bool,
+}
+
+impl Keyboard {
+    fn status(&self) -> u16 {
+        let mut status = 0;
+        if self.ready {
+            status |= KEY_READY;
+        }
+        if self.interrupt_enable {
+            status |= KEY_INTERRUPT_ENABLE;
+        }
+        status
+    }
+}
+
+impl Device for Keyboard {
+    fn range(&self) -> RangeInclusive<u16> {
+        KBSR as u16..=KBDR as u16
+    }
+
+    fn read(&mut self, address: u16, context: &mut DeviceContext) -> Result<u16, VmError> {
+        if address == KBDR as u16 {
+            if let Some(key) = context.read_input()? {
+                self.data = key as u16;
+            }
+            self.ready = false;
+            return Ok(self.data);
+        }
+        self.ready = context.input_ready()?;
+        Ok(self.peek(address))
+    }
+
+    fn write(&mut self, address: u16, value: u16, _: &mut DeviceContext) -> Result<(), VmError> {
+        if address == KBSR as u16 {
+            self.interrupt_enable = value & KEY_INTERRUPT_ENABLE != 0;
+        }
+        Ok(())
+    }
+
+    fn peek(&self, address: u16) -> u16 {
+        match address {
+            a if a == KBSR as u16 => self.status(),
+            a if a == KBDR as u16 => self.data,
+            _ => 0,
+        }
+    }
+
+    /// With interrupts enabled, keys are noticed without the program polling
+    fn tick(&mut self, context: &mut DeviceContext) -> Result<(), VmError> {
+        if self.interrupt_enable {
+            self.ready = context.input_ready()?;
+        }
+        Ok(())
+    }
+
+    fn interrupt(&self) -> Option<Interrupt> {
+        (self.interrupt_enable && self.ready).then_some(Interrupt {
+            vector: 0x80,
+            priority: 4,
+        })
+    }
+
+    /// Waiting keys stay on the console, only the registers are saved
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = vec![self.interrupt_enable as u8];
+        state.extend(self.data.to_be_bytes());
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), VmError> {
+        let [interrupt_enable, high, low] = *state else {
+            return Err(invalid_state("keyboard"));
+        };
+        self.interrupt_enable = interrupt_enable != 0;
+        self.data = u16::from_be_bytes([high, low]);
+        self.ready = false;
+        Ok(())
+    }
+}
+
+/// Display status (DSR) and data (DDR) registers. Characters written to DDR are printed
+/// right away, so DSR always reports the display as ready.
+#[derive(Debug, Default)]
+pub(crate) struct Display {
+    /// DDR keeps the last character written
+    data: u16,
+}
+
+impl Device for Display {
+    fn range(&self) -> RangeInclusive<u16> {
+        DSR as u16..=DDR as u16
+    }
+
+    fn read(&mut self, address: u16, _: &mut DeviceContext) -> Result<u16, VmError> {
+        Ok(self.peek(address))
+    }
+
+    fn write(
+        &mut self,
+        address: u16,
+        value: u16,
+        context: &mut DeviceContext,
+    ) -> Result<(), VmError> {
+        if address == DDR as u16 {
+            self.data = value;
+            context.write_output(value as u8)?;
+        }
+        Ok(())
+    }
+
+    fn peek(&self, address: u16) -> u16 {
+        match address {
+            a if a == DSR as u16 => DISPLAY_READY,
+            a if a == DDR as u16 => self.data,
+            _ => 0,
+        }
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.data.to_be_bytes().to_vec()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), VmError> {
+        let data: [u8; 2] = state.try_into().map_err(|_| invalid_state("display"))?;
+        self.data = u16::from_be_bytes(data);
+        Ok(())
+    }
+}
+
+/// Machine control register (MCR). Clearing bit 15 stops the clock, which is how the LC-3
+/// operating system implements HALT.
+#[derive(Debug)]
+pub(crate) struct MachineControl {
+    value: u16,
+}
+
+impl Default for MachineControl {
+    fn default() -> Self {
+        MachineControl {
+            value: CLOCK_ENABLE,
+        }
+    }
+}
+
+impl Device for MachineControl {
+    fn range(&self) -> RangeInclusive<u16> {
+        MCR as u16..=MCR as u16
+    }
+
+    fn read(&mut self, address: u16, _: &mut DeviceContext) -> Result<u16, VmError> {
+        Ok(self.peek(address))
+    }
+
+    fn write(&mut self, _: u16, value: u16, context: &mut DeviceContext) -> Result<(), VmError> {
+        self.value = value;
+        if value & CLOCK_ENABLE == 0 {
+            context.stop_clock();
+        }
+        Ok(())
+    }
+
+    fn peek(&self, _: u16) -> u16 {
+        self.value
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.value.to_be_bytes().to_vec()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), VmError> {
+        let value: [u8; 2] = state
+            .try_into()
+            .map_err(|_| invalid_state("machine control"))?;
+        self.value = u16::from_be_bytes(value);
+        Ok(())
+    }
+}
+
+fn invalid_state(device: &str) -> VmError {
+    VmError::InvalidSnapshot(format!("invalid {} state", device))
+}
+
+/// The registers every machine starts with, in the order their state is saved
+pub(crate) fn builtin_devices() -> Vec<Box<dyn Device>> {
+    vec![
+        Box::new(Keyboard::default()),
+        Box::new(Display::default()),
+        Box::new(MachineControl::default()),
+    ]
+}
@@ -0,0 +1,145 @@
+use crate::instruction::{Instruction, Operand};
+use std::collections::BTreeMap;
+
+/// Labels by address, used to print PC-relative operands symbolically
+pub type Labels = BTreeMap<u16, String>;
+
+/// Turn a 16-bit word into canonical LC-3 assembly text that reassembles to the same word,
+/// words that are not canonical instructions are printed as `.FILL`.
+/// PC-relative operands are printed as signed offsets since the address of the word is unknown.
+pub fn disassemble(word: u16) -> String {
+    render(word, None, &Labels::new())
+}
+
+/// Turn a word located at `address` into canonical LC-3 assembly text.
+/// PC-relative operands are resolved to their target, printed as a label when `labels` has one.
+pub fn disassemble_at(word: u16, address: u16, labels: &Labels) -> String {
+    render(word, Some(address), labels)
+}
+
+/// Labels for every branch and subroutine target that falls inside the image
+pub fn branch_labels(origin: u16, words: &[u16]) -> Labels {
+    let end = origin as usize + words.len();
+    let mut labels = Labels::new();
+    for (offset, &word) in words.iter().enumerate() {
+        let address = origin.wrapping_add(offset as u16);
+        if let Some(target) = branch_target(word, address) {
+            if (origin as usize..end).contains(&(target as usize)) {
+                labels.insert(target, format!("L{:04X}", target));
+            }
+        }
+    }
+    labels
+}
+
+/// Dump an image with addresses, raw hex and mnemonics, one word per line
+pub fn listing(origin: u16, words: &[u16]) -> String {
+    let labels = branch_labels(origin, words);
+    let mut out = String::new();
+    out.push_str(&format!("{:<16}.ORIG x{:04X}\n", "", origin));
+    for (offset, &word) in words.iter().enumerate() {
+        let address = origin.wrapping_add(offset as u16);
+        let label = labels.get(&address).map(String::as_str).unwrap_or("");
+        let mut line = format!(
+            "x{:04X}  x{:04X}  {:<8}{}",
+            address,
+            word,
+            label,
+            disassemble_at(word, address, &labels)
+        );
+        // Data words are disassembled too, show the character to make strings readable
+        if (0x20..0x7F).contains(&word) {
+            line.push_str(&format!("  ; '{}'", word as u8 as char));
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out.push_str(&format!("{:<16}.END\n", ""));
+    out
+}
+
+/// Target of a `BR` or `JSR` instruction located at `address`
+fn branch_target(word: u16, address: u16) -> Option<u16> {
+    let next = address.wrapping_add(1);
+    match canonical(word)? {
+        Instruction::Br { n, z, p, offset } if n || z || p => {
+            Some(next.wrapping_add(offset as u16))
+        }
+        Instruction::Jsr { offset } => Some(next.wrapping_add(offset as u16)),
+        _ => None,
+    }
+}
+
+fn render(word: u16, address: Option<u16>, labels: &Labels) -> String {
+    // PC-relative operand: a label, an absolute address or a bare offset
+    let target = |offset: i16| match address {
+        Some(address) => {
+            let target = address.wrapping_add(1).wrapping_add(offset as u16);
+            match labels.get(&target) {
+                Some(label) => label.clone(),
+                None => format!("x{:04X}", target),
+            }
+        }
+        None => format!("#{}", offset),
+    };
+    let operand = |operand: Operand| match operand {
+        Operand::Reg(sr2) => format!("R{}", sr2),
+        Operand::Imm(imm5) => format!("#{}", imm5),
+    };
+
+    // Words with stray bits in unused fields would reassemble to a different word
+    let Some(instruction) = canonical(word) else {
+        return fill(word);
+    };
+    match instruction {
+        Instruction::Add {
+            dr,
+            sr1,
+            operand: op,
+        } => {
+            format!("ADD R{}, R{}, {}", dr, sr1, operand(op))
+        }
+        Instruction::And {
+            dr,
+            sr1,
+            operand: op,
+        } => {
+            format!("AND R{}, R{}, {}", dr, sr1, operand(op))
+        }
+        Instruction::Not { dr, sr } => format!("NOT R{}, R{}", dr, sr),
+        Instruction::Br { n, z, p, offset } => {
+            if !(n || z || p) {
+                // No condition code tested, the branch is never taken and usually holds data
+                return fill(word);
+            }
+            let n = if n { "n" } else { "" };
+            let z = if z { "z" } else { "" };
+            let p = if p { "p" } else { "" };
+            format!("BR{}{}{} {}", n, z, p, target(offset))
+        }
+        Instruction::Jmp { base: 7 } => "RET".to_string(),
+        Instruction::Jmp { base } => format!("JMP R{}", base),
+        Instruction::Jsr { offset } => format!("JSR {}", target(offset)),
+        Instruction::Jsrr { base } => format!("JSRR R{}", base),
+        Instruction::Ld { dr, offset } => format!("LD R{}, {}", dr, target(offset)),
+        Instruction::Ldi { dr, offset } => format!("LDI R{}, {}", dr, target(offset)),
+        Instruction::Lea { dr, offset } => format!("LEA R{}, {}", dr, target(offset)),
+        Instruction::St { sr, offset } => format!("ST R{}, {}", sr, target(offset)),
+        Instruction::Sti { sr, offset } => format!("STI R{}, {}", sr, target(offset)),
+        Instruction::Ldr { dr, base, offset } => format!("LDR R{}, R{}, #{}", dr, base, offset),
+        Instruction::Str { sr, base, offset } => format!("STR R{}, R{}, #{}", sr, base, offset),
+        Instruction::Rti => "RTI".to_string(),
+        Instruction::Trap { vector } => format!("TRAP x{:02X}", vector),
+        Instruction::Reserved(_) => fill(word),
+    }
+}
+
+/// The instruction `word` decodes to, if encoding it gives `word` back
+fn canonical(word: u16) -> Option<Instruction> {
+    let instruction = Instruction::decode(word);
+    (instruction.encode() == word).then_some(instruction)
+}
+
+fn fill(word: u16) -> String {
+    format!(".FILL x{:04X}", word)
+}
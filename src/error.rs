@@ -0,0 +1,78 @@
+use std::fmt;
+use std::io;
+
+/// Faults raised while loading or executing a program. They are propagated to the embedding
+/// host instead of terminating the process, so the machine state can still be inspected.
+#[derive(Debug)]
+pub enum VmError {
+    /// The instruction word holds an opcode the machine cannot execute (e.g. the reserved opcode)
+    IllegalOpcode(u16),
+    /// A `TRAP` instruction was executed with a vector that has no trap routine
+    UnknownTrap(u16),
+    /// A privileged instruction (`RTI`) was executed in user mode
+    PrivilegeViolation(u16),
+    /// User mode code accessed system space (0x0000 - 0x2FFF) or the I/O page
+    AccessViolation(u16),
+    /// The machine executed `HALT` and cannot be stepped any further
+    Halted,
+    /// The image does not fit in memory when loaded at its origin address
+    ImageTooLarge { origin: u16 },
+    /// The program asked for input at a different cycle than the replayed log recorded
+    ReplayDiverged { cycle: u64, expected: u64 },
+    /// A snapshot file is malformed or was written by an unsupported version
+    InvalidSnapshot(String),
+    /// A device was attached at an address outside the I/O page or handled by another device
+    DeviceConflict(u16),
+    /// Reading the image or talking to the console failed
+    Io(io::Error),
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmError::IllegalOpcode(instruction) => {
+                write!(f, "illegal opcode in instruction {:#06x}", instruction)
+            }
+            VmError::UnknownTrap(vector) => write!(f, "unknown trap vector {:#04x}", vector),
+            VmError::PrivilegeViolation(instruction) => write!(
+                f,
+                "privilege mode violation executing instruction {:#06x}",
+                instruction
+            ),
+            VmError::AccessViolation(address) => write!(
+                f,
+                "access control violation: user mode access to {:#06x}",
+                address
+            ),
+            VmError::Halted => write!(f, "the machine is halted"),
+            VmError::ImageTooLarge { origin } => {
+                write!(f, "image loaded at {:#06x} does not fit in memory", origin)
+            }
+            VmError::ReplayDiverged { cycle, expected } => write!(
+                f,
+                "input replay diverged: input read at cycle {} but the log expects cycle {}",
+                cycle, expected
+            ),
+            VmError::InvalidSnapshot(message) => write!(f, "invalid snapshot: {}", message),
+            VmError::DeviceConflict(address) => {
+                write!(f, "cannot attach a device at {:#06x}", address)
+            }
+            VmError::Io(e) => write!(f, "i/o error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for VmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VmError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for VmError {
+    fn from(e: io::Error) -> Self {
+        VmError::Io(e)
+    }
+}
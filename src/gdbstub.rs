@@ -0,0 +1,438 @@
+use crate::register::LC3CPURegister::{self, *};
+use crate::vm::Vm;
+use crate::watch::{WatchHit, WatchKind, Watchpoint};
+use std::collections::BTreeSet;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+/// Registers in the order of the `g` packet
+const REGISTERS: [LC3CPURegister; 10] = [R0, R1, R2, R3, R4, R5, R6, R7, PC, PSR];
+const REGISTER_COUNT: usize = REGISTERS.len();
+
+/// Signal reported when execution stops at a breakpoint or after a single step
+const SIGTRAP: u8 = 5;
+/// Signal reported when the client interrupts a running program
+const SIGINT: u8 = 2;
+/// Signal reported when the program faults
+const SIGILL: u8 = 4;
+
+/// Largest packet payload advertised to the client, replies never exceed it either
+const PACKET_SIZE: usize = 0x4000;
+
+/// Instructions executed between checks for an interrupt request from the client
+const INTERRUPT_POLL_INTERVAL: u32 = 1024;
+
+/// Why execution stopped, reported to the client as a stop reply
+enum Stop {
+    Signal(u8),
+    /// A watchpoint was triggered
+    Watch(WatchHit),
+    /// The program executed `HALT`
+    Exited,
+}
+
+/// GDB remote serial protocol stub driving a [`Vm`].
+///
+/// Memory is byte addressed as GDB expects: LC-3 word `w` is stored little-endian at bytes
+/// `2w` and `2w + 1`. Registers are 16-bit and sent little-endian, PSR holds the condition codes.
+#[derive(Debug)]
+pub struct GdbStub {
+    vm: Vm,
+    breakpoints: BTreeSet<u16>,
+}
+
+impl GdbStub {
+    pub fn new(vm: Vm) -> Self {
+        GdbStub {
+            vm,
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    pub fn vm(&self) -> &Vm {
+        &self.vm
+    }
+
+    /// Accept a single client on `listener` and serve it until it detaches or kills the program
+    pub fn listen(&mut self, listener: &TcpListener) -> io::Result<()> {
+        let (stream, _) = listener.accept()?;
+        self.serve(stream)
+    }
+
+    /// Serve a connected client until it detaches, kills the program or disconnects
+    pub fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        let mut connection = Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+        };
+        while let Some(packet) = connection.read_packet()? {
+            let reply = match packet.first() {
+                Some(b'?') => self.stop_reply(Stop::Signal(SIGTRAP)),
+                Some(b'g') => self.read_registers(),
+                Some(b'G') => self.write_registers(&packet[1..]),
+                Some(b'p') => self.read_register(&packet[1..]),
+                Some(b'P') => self.write_register(&packet[1..]),
+                Some(b'm') => self.read_memory(&packet[1..]),
+                Some(b'M') => self.write_memory(&packet[1..]),
+                Some(b'Z') => self.breakpoint(&packet[1..], true),
+                Some(b'z') => self.breakpoint(&packet[1..], false),
+                Some(b's') => {
+                    let stop = self.step(&packet[1..]);
+                    self.stop_reply(stop)
+                }
+                Some(b'c') => {
+                    let stop = self.resume(&packet[1..], &connection)?;
+                    self.stop_reply(stop)
+                }
+                Some(b'k') => return Ok(()),
+                Some(b'D') => {
+                    connection.write_packet("OK")?;
+                    return Ok(());
+                }
+                _ if packet.starts_with(b"qSupported") => format!("PacketSize={:x}", PACKET_SIZE),
+                _ if packet == b"qAttached" => "1".to_string(),
+                _ => String::new(),
+            };
+            connection.write_packet(&reply)?;
+        }
+        Ok(())
+    }
+
+    fn stop_reply(&self, stop: Stop) -> String {
+        match stop {
+            Stop::Signal(signal) => format!("S{:02x}", signal),
+            Stop::Watch(hit) => {
+                // Report the kind of the watchpoint that matched, not the kind of access
+                let kind = self
+                    .vm
+                    .watchpoints()
+                    .iter()
+                    .find(|watchpoint| watchpoint.matches(hit.kind, hit.address, hit.new))
+                    .map_or(hit.kind, |watchpoint| watchpoint.kind);
+                let name = match kind {
+                    WatchKind::Write => "watch",
+                    WatchKind::Read => "rwatch",
+                    WatchKind::Access => "awatch",
+                };
+                format!("T{:02x}{}:{:x};", SIGTRAP, name, hit.address as usize * 2)
+            }
+            Stop::Exited => "W00".to_string(),
+        }
+    }
+
+    fn register_value(&self, index: usize) -> u16 {
+        self.vm.register(REGISTERS[index])
+    }
+
+    fn read_registers(&self) -> String {
+        (0..REGISTER_COUNT)
+            .map(|index| encode_word(self.register_value(index)))
+            .collect()
+    }
+
+    fn write_registers(&mut self, data: &[u8]) -> String {
+        if data.len() != REGISTER_COUNT * 4 {
+            return "E01".to_string();
+        }
+        // Decode every value before changing any register
+        let Some(values) = data.chunks(4).map(decode_word).collect::<Option<Vec<_>>>() else {
+            return "E01".to_string();
+        };
+        for (register, value) in REGISTERS.into_iter().zip(values) {
+            self.vm.set_register(register, value);
+        }
+        "OK".to_string()
+    }
+
+    fn read_register(&self, data: &[u8]) -> String {
+        match parse_hex(data) {
+            Some(index) if index < REGISTER_COUNT => encode_word(self.register_value(index)),
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn write_register(&mut self, data: &[u8]) -> String {
+        let Some((index, value)) = split_once(data, b'=') else {
+            return "E01".to_string();
+        };
+        match (parse_hex(index), decode_word(value)) {
+            (Some(index), Some(value)) if index < REGISTER_COUNT => {
+                self.vm.set_register(REGISTERS[index], value);
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn read_memory(&self, data: &[u8]) -> String {
+        let Some((address, length)) = parse_range(data) else {
+            return "E01".to_string();
+        };
+        // Two hex digits per byte
+        if length > PACKET_SIZE / 2 {
+            return "E01".to_string();
+        }
+        (address..address + length)
+            .map(|byte| format!("{:02x}", self.memory_byte(byte)))
+            .collect()
+    }
+
+    fn write_memory(&mut self, data: &[u8]) -> String {
+        let Some((range, bytes)) = split_once(data, b':') else {
+            return "E01".to_string();
+        };
+        let Some((address, length)) = parse_range(range) else {
+            return "E01".to_string();
+        };
+        if bytes.len() != length * 2 {
+            return "E01".to_string();
+        }
+        let Some(values) = bytes.chunks(2).map(hex_byte).collect::<Option<Vec<_>>>() else {
+            return "E01".to_string();
+        };
+        for (offset, value) in values.into_iter().enumerate() {
+            let byte = address + offset;
+            let word_address = (byte / 2) as u16;
+            let word = self.vm.memory(word_address);
+            let word = if byte.is_multiple_of(2) {
+                (word & 0xFF00) | value as u16
+            } else {
+                (word & 0x00FF) | (value as u16) << 8
+            };
+            self.vm.set_memory(word_address, word);
+        }
+        "OK".to_string()
+    }
+
+    fn memory_byte(&self, byte: usize) -> u8 {
+        let word = self.vm.memory((byte / 2) as u16);
+        if byte.is_multiple_of(2) {
+            word as u8
+        } else {
+            (word >> 8) as u8
+        }
+    }
+
+    /// `Z<type>,addr,length` / `z<type>,addr,length`: software breakpoints (type 0) and
+    /// write, read or access watchpoints (types 2, 3 and 4) on byte addresses
+    fn breakpoint(&mut self, data: &[u8], insert: bool) -> String {
+        let mut fields = data.split(|&byte| byte == b',');
+        let (Some(kind), Some(address), Some(length), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return "E01".to_string();
+        };
+        let (Some(address), Some(length)) = (parse_hex(address), parse_hex(length)) else {
+            return "E01".to_string();
+        };
+        if address >= 2 * crate::constant::MEMORY_MAX {
+            return "E01".to_string();
+        }
+        let start = (address / 2) as u16;
+        let kind = match kind {
+            b"0" => {
+                if insert {
+                    self.breakpoints.insert(start);
+                } else {
+                    self.breakpoints.remove(&start);
+                }
+                return "OK".to_string();
+            }
+            b"2" => WatchKind::Write,
+            b"3" => WatchKind::Read,
+            b"4" => WatchKind::Access,
+            _ => return String::new(),
+        };
+        let Some(last) = address.checked_add(length.max(1) - 1) else {
+            return "E01".to_string();
+        };
+        let end = (last / 2).min(0xFFFF) as u16;
+        let watchpoint = Watchpoint::range(start, end, kind);
+        if insert {
+            self.vm.add_watchpoint(watchpoint);
+        } else if let Some(index) = self.vm.watchpoints().iter().position(|w| *w == watchpoint) {
+            self.vm.remove_watchpoint(index);
+        }
+        "OK".to_string()
+    }
+
+    /// `s [addr]`: execute one instruction
+    fn step(&mut self, data: &[u8]) -> Stop {
+        self.jump(data);
+        self.execute_one()
+    }
+
+    /// `c [addr]`: run until a breakpoint, HALT, a fault or an interrupt from the client
+    fn resume(&mut self, data: &[u8], connection: &Connection) -> io::Result<Stop> {
+        self.jump(data);
+        let mut until_poll = INTERRUPT_POLL_INTERVAL;
+        loop {
+            if let Some(stop) = self.execute_one_or_stop() {
+                return Ok(stop);
+            }
+            if self.breakpoints.contains(&self.vm.register(PC)) {
+                return Ok(Stop::Signal(SIGTRAP));
+            }
+            until_poll -= 1;
+            if until_poll == 0 {
+                until_poll = INTERRUPT_POLL_INTERVAL;
+                if connection.interrupted()? {
+                    return Ok(Stop::Signal(SIGINT));
+                }
+            }
+        }
+    }
+
+    /// Optional resume address of `s` and `c` packets
+    fn jump(&mut self, data: &[u8]) {
+        if let Some(address) = parse_hex(data) {
+            self.vm.set_register(PC, (address / 2) as u16);
+        }
+    }
+
+    fn execute_one(&mut self) -> Stop {
+        self.execute_one_or_stop().unwrap_or(Stop::Signal(SIGTRAP))
+    }
+
+    /// Execute an instruction, returns why the program cannot continue if it cannot
+    fn execute_one_or_stop(&mut self) -> Option<Stop> {
+        match self.vm.step() {
+            Ok(outcome) if outcome.halted => Some(Stop::Exited),
+            Ok(outcome) => outcome.watch_hits.first().map(|&hit| Stop::Watch(hit)),
+            Err(_) if self.vm.is_halted() => Some(Stop::Exited),
+            Err(_) => Some(Stop::Signal(SIGILL)),
+        }
+    }
+}
+
+/// Packet framing over the client connection
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+impl Connection {
+    /// Read the next `$payload#checksum` packet and acknowledge it, `None` once disconnected
+    fn read_packet(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            // Skip acknowledgements and interrupt requests sent while the program was stopped
+            let Some(byte) = self.read_byte()? else {
+                return Ok(None);
+            };
+            if byte != b'$' {
+                continue;
+            }
+            let mut payload = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(byte) => payload.push(byte),
+                    None => return Ok(None),
+                }
+            }
+            let mut checksum = [0; 2];
+            self.reader.read_exact(&mut checksum)?;
+            if hex_byte(&checksum) != Some(checksum_of(&payload)) {
+                self.writer.write_all(b"-")?;
+                self.writer.flush()?;
+                continue;
+            }
+            self.writer.write_all(b"+")?;
+            self.writer.flush()?;
+            return Ok(Some(payload));
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0; 1];
+        match self.reader.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    fn write_packet(&mut self, payload: &str) -> io::Result<()> {
+        write!(
+            self.writer,
+            "${}#{:02x}",
+            payload,
+            checksum_of(payload.as_bytes())
+        )?;
+        self.writer.flush()
+    }
+
+    /// Whether the client sent an interrupt request (`0x03`) while the program was running
+    fn interrupted(&self) -> io::Result<bool> {
+        let stream = self.reader.get_ref();
+        stream.set_nonblocking(true)?;
+        let mut byte = [0; 1];
+        let result = stream.peek(&mut byte);
+        stream.set_nonblocking(false)?;
+        match result {
+            Ok(1) if byte[0] == 0x03 => {
+                (&*stream).read_exact(&mut byte)?;
+                Ok(true)
+            }
+            Ok(_) => Ok(false),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn checksum_of(payload: &[u8]) -> u8 {
+    payload
+        .iter()
+        .fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+/// Registers are sent in target byte order, little-endian
+fn encode_word(value: u16) -> String {
+    format!("{:02x}{:02x}", value & 0xFF, value >> 8)
+}
+
+fn decode_word(hex: &[u8]) -> Option<u16> {
+    if hex.len() != 4 {
+        return None;
+    }
+    let low = hex_byte(&hex[..2])?;
+    let high = hex_byte(&hex[2..])?;
+    Some((high as u16) << 8 | low as u16)
+}
+
+/// Two hexadecimal digits
+fn hex_byte(hex: &[u8]) -> Option<u8> {
+    match *hex {
+        [high, low] => Some(hex_digit(high)? << 4 | hex_digit(low)?),
+        _ => None,
+    }
+}
+
+fn hex_digit(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|digit| digit as u8)
+}
+
+/// A non-empty hexadecimal number, `None` when it does not fit in `usize`
+fn parse_hex(hex: &[u8]) -> Option<usize> {
+    if hex.is_empty() {
+        return None;
+    }
+    hex.iter().try_fold(0usize, |value, &byte| {
+        value
+            .checked_mul(16)?
+            .checked_add(hex_digit(byte)? as usize)
+    })
+}
+
+fn split_once(data: &[u8], separator: u8) -> Option<(&[u8], &[u8])> {
+    let index = data.iter().position(|&byte| byte == separator)?;
+    Some((&data[..index], &data[index + 1..]))
+}
+
+/// `addr,length` in hexadecimal, limited to the 128KB of byte addressable memory
+fn parse_range(data: &[u8]) -> Option<(usize, usize)> {
+    let (address, length) = split_once(data, b',')?;
+    let (address, length) = (parse_hex(address)?, parse_hex(length)?);
+    (address.checked_add(length)? <= 2 * crate::constant::MEMORY_MAX).then_some((address, length))
+}
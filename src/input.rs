@@ -0,0 +1,49 @@
+use std::io::{self, BufRead, Write};
+
+/// First line of an input log
+const HEADER: &str = "# lc3-vm input log v1";
+
+/// A keyboard byte and the cycle of the instruction that first saw it on the console, either
+/// by polling KBSR, reading KBDR or through a trap routine
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputEvent {
+    pub cycle: u64,
+    pub byte: u8,
+}
+
+/// Write an input log, one `cycle byte` pair per line in decimal so logs can be edited by hand
+pub fn write_log<W: Write>(events: &[InputEvent], mut writer: W) -> io::Result<()> {
+    writeln!(writer, "{}", HEADER)?;
+    for event in events {
+        writeln!(writer, "{} {}", event.cycle, event.byte)?;
+    }
+    writer.flush()
+}
+
+/// Read an input log written by [`write_log`]. Blank lines and `#` comments are skipped.
+pub fn read_log<R: BufRead>(reader: R) -> io::Result<Vec<InputEvent>> {
+    let mut events = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let event = match line.split_whitespace().collect::<Vec<_>>()[..] {
+            [cycle, byte] => cycle
+                .parse()
+                .ok()
+                .zip(byte.parse().ok())
+                .map(|(cycle, byte)| InputEvent { cycle, byte }),
+            _ => None,
+        };
+        let event = event.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: expected `cycle byte`", index + 1),
+            )
+        })?;
+        events.push(event);
+    }
+    Ok(events)
+}
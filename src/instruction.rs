@@ -1,6 +1,8 @@
 use crate::constant;
+use crate::constant::IMMEDIATE_MODE;
+use crate::error::VmError;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[allow(non_camel_case_types)]
 pub enum LC3Instruction {
     ADD,  /* add ( DR, SR1, mode, SR2 | imm5) */
@@ -11,7 +13,7 @@ pub enum LC3Instruction {
     JSR,  /* jump register */
     LDR,  /* load register */
     STR,  /* store register */
-    RTI,  /* unused */
+    RTI,  /* return from interrupt */
     NOT,  /* bitwise not */
     LDI,  /* load indirect */
     STI,  /* store indirect */
@@ -23,11 +25,11 @@ pub enum LC3Instruction {
 
 impl LC3Instruction {
     /// Decodes machine code bytes from the iterator to an Instruction.
-    pub fn from_bytes(instruction_bytes: u16) -> Option<Self> {
+    pub fn from_bytes(instruction_bytes: u16) -> Result<Self, VmError> {
         let right_shift_val = constant::CPU_INSTRUCTION_BIT_WIDTH - constant::CPU_OPCODE_BIT_SIZE;
         let opcode: u16 = instruction_bytes >> right_shift_val;
         // Opcode is a first 4 bits
-        Some(match opcode {
+        Ok(match opcode {
             0b1101 => LC3Instruction::RES,
             0b0001 => LC3Instruction::ADD,
             0b0101 => LC3Instruction::AND,
@@ -44,7 +46,238 @@ impl LC3Instruction {
             0b1011 => LC3Instruction::STI,
             0b0111 => LC3Instruction::STR,
             0b1111 => LC3Instruction::TRAP,
-            _ => panic!("No opcode found {}", opcode),
+            _ => return Err(VmError::IllegalOpcode(instruction_bytes)),
         })
     }
 }
+
+/// Second source operand of `ADD` and `AND`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    /// Register mode: SR2
+    Reg(u16),
+    /// Immediate mode: sign-extended imm5
+    Imm(i16),
+}
+
+/// A fully decoded instruction. Registers are indexes (0 - 7) and offsets are already sign
+/// extended, PC-relative offsets are relative to the incremented PC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    Add {
+        dr: u16,
+        sr1: u16,
+        operand: Operand,
+    },
+    And {
+        dr: u16,
+        sr1: u16,
+        operand: Operand,
+    },
+    Br {
+        n: bool,
+        z: bool,
+        p: bool,
+        offset: i16,
+    },
+    /// `RET` is `JMP R7`
+    Jmp {
+        base: u16,
+    },
+    Jsr {
+        offset: i16,
+    },
+    Jsrr {
+        base: u16,
+    },
+    Ld {
+        dr: u16,
+        offset: i16,
+    },
+    Ldi {
+        dr: u16,
+        offset: i16,
+    },
+    Ldr {
+        dr: u16,
+        base: u16,
+        offset: i16,
+    },
+    Lea {
+        dr: u16,
+        offset: i16,
+    },
+    Not {
+        dr: u16,
+        sr: u16,
+    },
+    Rti,
+    St {
+        sr: u16,
+        offset: i16,
+    },
+    Sti {
+        sr: u16,
+        offset: i16,
+    },
+    Str {
+        sr: u16,
+        base: u16,
+        offset: i16,
+    },
+    Trap {
+        vector: u8,
+    },
+    /// The reserved opcode, the raw word is kept since it has no defined fields
+    Reserved(u16),
+}
+
+/// Sign extend the low `bits` bits of `word`
+fn field(word: u16, bits: u32) -> i16 {
+    let shift = 16 - bits;
+    ((word << shift) as i16) >> shift
+}
+
+/// Truncate a signed value to its low `bits` bits
+fn bits(value: i16, bits: u32) -> u16 {
+    value as u16 & ((1 << bits) - 1)
+}
+
+impl Instruction {
+    /// Decode a 16-bit word. Every word decodes to an instruction, bits the ISA leaves
+    /// unspecified are ignored.
+    pub fn decode(word: u16) -> Self {
+        let r9 = (word >> 9) & 0x7;
+        let r6 = (word >> 6) & 0x7;
+        let operand = || {
+            if (word >> 5) & 0x1 == IMMEDIATE_MODE {
+                Operand::Imm(field(word, 5))
+            } else {
+                Operand::Reg(word & 0x7)
+            }
+        };
+        let right_shift_val = constant::CPU_INSTRUCTION_BIT_WIDTH - constant::CPU_OPCODE_BIT_SIZE;
+        match word >> right_shift_val {
+            0b0001 => Instruction::Add {
+                dr: r9,
+                sr1: r6,
+                operand: operand(),
+            },
+            0b0101 => Instruction::And {
+                dr: r9,
+                sr1: r6,
+                operand: operand(),
+            },
+            0b0000 => Instruction::Br {
+                n: word & 0x0800 != 0,
+                z: word & 0x0400 != 0,
+                p: word & 0x0200 != 0,
+                offset: field(word, 9),
+            },
+            0b1100 => Instruction::Jmp { base: r6 },
+            0b0100 if (word >> 11) & 0x1 == IMMEDIATE_MODE => Instruction::Jsr {
+                offset: field(word, 11),
+            },
+            0b0100 => Instruction::Jsrr { base: r6 },
+            0b0010 => Instruction::Ld {
+                dr: r9,
+                offset: field(word, 9),
+            },
+            0b1010 => Instruction::Ldi {
+                dr: r9,
+                offset: field(word, 9),
+            },
+            0b0110 => Instruction::Ldr {
+                dr: r9,
+                base: r6,
+                offset: field(word, 6),
+            },
+            0b1110 => Instruction::Lea {
+                dr: r9,
+                offset: field(word, 9),
+            },
+            0b1001 => Instruction::Not { dr: r9, sr: r6 },
+            0b1000 => Instruction::Rti,
+            0b0011 => Instruction::St {
+                sr: r9,
+                offset: field(word, 9),
+            },
+            0b1011 => Instruction::Sti {
+                sr: r9,
+                offset: field(word, 9),
+            },
+            0b0111 => Instruction::Str {
+                sr: r9,
+                base: r6,
+                offset: field(word, 6),
+            },
+            0b1111 => Instruction::Trap {
+                vector: (word & 0xFF) as u8,
+            },
+            _ => Instruction::Reserved(word),
+        }
+    }
+
+    /// Encode back to a 16-bit word, unspecified bits are written in their canonical form
+    pub fn encode(&self) -> u16 {
+        let operand = |operand: &Operand| match *operand {
+            Operand::Reg(sr2) => sr2 & 0x7,
+            Operand::Imm(imm5) => 1 << 5 | bits(imm5, 5),
+        };
+        match self {
+            Instruction::Add {
+                dr,
+                sr1,
+                operand: op,
+            } => 0x1000 | dr << 9 | sr1 << 6 | operand(op),
+            Instruction::And {
+                dr,
+                sr1,
+                operand: op,
+            } => 0x5000 | dr << 9 | sr1 << 6 | operand(op),
+            Instruction::Br { n, z, p, offset } => {
+                (*n as u16) << 11 | (*z as u16) << 10 | (*p as u16) << 9 | bits(*offset, 9)
+            }
+            Instruction::Jmp { base } => 0xC000 | base << 6,
+            Instruction::Jsr { offset } => 0x4800 | bits(*offset, 11),
+            Instruction::Jsrr { base } => 0x4000 | base << 6,
+            Instruction::Ld { dr, offset } => 0x2000 | dr << 9 | bits(*offset, 9),
+            Instruction::Ldi { dr, offset } => 0xA000 | dr << 9 | bits(*offset, 9),
+            Instruction::Ldr { dr, base, offset } => {
+                0x6000 | dr << 9 | base << 6 | bits(*offset, 6)
+            }
+            Instruction::Lea { dr, offset } => 0xE000 | dr << 9 | bits(*offset, 9),
+            Instruction::Not { dr, sr } => 0x903F | dr << 9 | sr << 6,
+            Instruction::Rti => 0x8000,
+            Instruction::St { sr, offset } => 0x3000 | sr << 9 | bits(*offset, 9),
+            Instruction::Sti { sr, offset } => 0xB000 | sr << 9 | bits(*offset, 9),
+            Instruction::Str { sr, base, offset } => {
+                0x7000 | sr << 9 | base << 6 | bits(*offset, 6)
+            }
+            Instruction::Trap { vector } => 0xF000 | *vector as u16,
+            Instruction::Reserved(word) => *word,
+        }
+    }
+
+    /// The opcode this instruction is encoded with
+    pub fn opcode(&self) -> LC3Instruction {
+        match self {
+            Instruction::Add { .. } => LC3Instruction::ADD,
+            Instruction::And { .. } => LC3Instruction::AND,
+            Instruction::Br { .. } => LC3Instruction::BR,
+            Instruction::Jmp { .. } => LC3Instruction::JMP,
+            Instruction::Jsr { .. } | Instruction::Jsrr { .. } => LC3Instruction::JSR,
+            Instruction::Ld { .. } => LC3Instruction::LD,
+            Instruction::Ldi { .. } => LC3Instruction::LDI,
+            Instruction::Ldr { .. } => LC3Instruction::LDR,
+            Instruction::Lea { .. } => LC3Instruction::LEA,
+            Instruction::Not { .. } => LC3Instruction::NOT,
+            Instruction::Rti => LC3Instruction::RTI,
+            Instruction::St { .. } => LC3Instruction::ST,
+            Instruction::Sti { .. } => LC3Instruction::STI,
+            Instruction::Str { .. } => LC3Instruction::STR,
+            Instruction::Trap { .. } => LC3Instruction::TRAP,
+            Instruction::Reserved(_) => LC3Instruction::RES,
+        }
+    }
+}
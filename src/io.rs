@@ -0,0 +1,333 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::thread;
+
+/// Console the machine reads keyboard bytes from and writes display bytes to.
+/// Reads never block so the machine keeps running while a program polls the keyboard.
+pub trait IoBackend: Send {
+    /// Next input byte, `None` when no byte is available yet
+    fn read_byte(&mut self) -> io::Result<Option<u8>>;
+
+    /// Whether [`IoBackend::read_byte`] would return a byte, the byte is left in place. The
+    /// keyboard status register is answered with it.
+    fn poll(&mut self) -> io::Result<bool>;
+
+    fn write_byte(&mut self, byte: u8) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()>;
+
+    /// Wait for the next input byte, used by the trap routines that read the keyboard
+    fn read_byte_blocking(&mut self) -> io::Result<u8> {
+        loop {
+            if let Some(byte) = self.read_byte()? {
+                return Ok(byte);
+            }
+            thread::yield_now();
+        }
+    }
+}
+
+impl fmt::Debug for dyn IoBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("IoBackend")
+    }
+}
+
+/// Output written by an in-memory backend, shared so it can be read after the backend was
+/// handed to the machine
+pub type SharedOutput = Arc<Mutex<Vec<u8>>>;
+
+/// The process console: stdin and stdout.
+/// Stdin is read through the process-wide reader also used by [`SharedStdin`], one byte at a
+/// time and only once the program asked for input, so bytes the program never reads are left
+/// on stdin for the host.
+#[derive(Debug, Default)]
+pub struct Terminal;
+
+impl Terminal {
+    pub fn new() -> Self {
+        Terminal
+    }
+}
+
+/// The only reader of the process stdin. A background thread reads one byte for every
+/// request, a byte read ahead for a keyboard poll stays here until the program or a command
+/// prompt takes it.
+#[derive(Debug)]
+struct StdinReader {
+    requests: Sender<()>,
+    bytes: Receiver<io::Result<u8>>,
+    /// A byte was requested from the reader thread and has not arrived yet
+    requested: bool,
+    pending: Option<u8>,
+}
+
+impl StdinReader {
+    /// The reader shared by the whole process, started on first use
+    fn get() -> MutexGuard<'static, StdinReader> {
+        static READER: OnceLock<Mutex<StdinReader>> = OnceLock::new();
+        READER
+            .get_or_init(|| Mutex::new(StdinReader::spawn()))
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Thread reading one stdin byte for every request it receives
+    fn spawn() -> Self {
+        let (request_tx, request_rx) = mpsc::channel::<()>();
+        let (byte_tx, byte_rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut stdin = io::stdin();
+            for () in request_rx {
+                let mut buffer = [0; 1];
+                let result = stdin.read_exact(&mut buffer).map(|_| buffer[0]);
+                let failed = result.is_err();
+                if byte_tx.send(result).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        StdinReader {
+            requests: request_tx,
+            bytes: byte_rx,
+            requested: false,
+            pending: None,
+        }
+    }
+
+    /// Ask the reader thread for a byte unless one is on its way, then collect it when it
+    /// arrived. With `block` set, waits for it.
+    fn fill(&mut self, block: bool) -> io::Result<()> {
+        if self.pending.is_some() {
+            return Ok(());
+        }
+        if !self.requested {
+            self.requests.send(()).map_err(|_| stdin_closed())?;
+            self.requested = true;
+        }
+        let received = if block {
+            self.bytes.recv().map_err(|_| TryRecvError::Disconnected)
+        } else {
+            self.bytes.try_recv()
+        };
+        match received {
+            Ok(byte) => {
+                self.requested = false;
+                self.pending = Some(byte?);
+                Ok(())
+            }
+            Err(TryRecvError::Empty) => Ok(()),
+            Err(TryRecvError::Disconnected) => Err(stdin_closed()),
+        }
+    }
+}
+
+impl IoBackend for Terminal {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut stdin = StdinReader::get();
+        stdin.fill(false)?;
+        Ok(stdin.pending.take())
+    }
+
+    fn poll(&mut self) -> io::Result<bool> {
+        let mut stdin = StdinReader::get();
+        stdin.fill(false)?;
+        Ok(stdin.pending.is_some())
+    }
+
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        io::stdout().write_all(&[byte])
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+
+    fn read_byte_blocking(&mut self) -> io::Result<u8> {
+        let mut stdin = StdinReader::get();
+        stdin.fill(true)?;
+        Ok(stdin.pending.take().expect("blocking fill yields a byte"))
+    }
+}
+
+/// Stdin for a command prompt that shares stdin with the program it drives, such as the
+/// debugger REPL. Both read through the same reader, a byte read ahead when the program
+/// polled the keyboard is the next byte of the prompt unless the program took it.
+#[derive(Debug, Default)]
+pub struct SharedStdin {
+    byte: Option<u8>,
+}
+
+impl SharedStdin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Read for SharedStdin {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for SharedStdin {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.byte.is_none() {
+            let mut stdin = StdinReader::get();
+            match stdin.fill(true) {
+                Ok(()) => self.byte = stdin.pending.take(),
+                // The end of stdin is the end of the commands
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(self.byte.as_slice())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if amt > 0 {
+            self.byte = None;
+        }
+    }
+}
+
+/// Headless console reading from a buffer and collecting output in memory.
+/// Past the end of the input the keyboard reports no key, while waiting for a key fails like
+/// reading a closed stdin.
+#[derive(Debug, Default)]
+pub struct BufferIo {
+    input: VecDeque<u8>,
+    output: SharedOutput,
+}
+
+impl BufferIo {
+    pub fn new(input: impl Into<Vec<u8>>) -> Self {
+        BufferIo {
+            input: input.into().into(),
+            output: SharedOutput::default(),
+        }
+    }
+
+    /// Handle to everything the program writes
+    pub fn output(&self) -> SharedOutput {
+        Arc::clone(&self.output)
+    }
+}
+
+impl IoBackend for BufferIo {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.input.pop_front())
+    }
+
+    fn poll(&mut self) -> io::Result<bool> {
+        Ok(!self.input.is_empty())
+    }
+
+    fn read_byte_blocking(&mut self) -> io::Result<u8> {
+        self.input.pop_front().ok_or_else(end_of_input)
+    }
+
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        self.output.lock().unwrap().push(byte);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Headless console whose input arrives over time: each chunk of bytes only becomes
+/// available after the program polled or read the keyboard a given number of times without
+/// finding a byte. Useful to exercise programs that busy-wait on the keyboard.
+#[derive(Debug, Default)]
+pub struct ScriptedIo {
+    /// Chunks still to be delivered with the number of empty polls before each one
+    script: VecDeque<(u64, Vec<u8>)>,
+    available: VecDeque<u8>,
+    /// Empty polls since the previous chunk was delivered
+    idle: u64,
+    output: SharedOutput,
+}
+
+impl ScriptedIo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deliver `bytes` once the keyboard was polled `polls` times without input after the
+    /// previous chunk
+    pub fn after(mut self, polls: u64, bytes: impl Into<Vec<u8>>) -> Self {
+        self.script.push_back((polls, bytes.into()));
+        self
+    }
+
+    /// Handle to everything the program writes
+    pub fn output(&self) -> SharedOutput {
+        Arc::clone(&self.output)
+    }
+
+    fn fill(&mut self) {
+        while self.available.is_empty() {
+            let Some((polls, _)) = self.script.front() else {
+                return;
+            };
+            if self.idle < *polls {
+                self.idle += 1;
+                return;
+            }
+            let (_, bytes) = self.script.pop_front().unwrap();
+            self.available.extend(bytes);
+            self.idle = 0;
+        }
+    }
+}
+
+impl IoBackend for ScriptedIo {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        self.fill();
+        Ok(self.available.pop_front())
+    }
+
+    fn poll(&mut self) -> io::Result<bool> {
+        self.fill();
+        Ok(!self.available.is_empty())
+    }
+
+    /// Fails once the script is exhausted, no more bytes would ever arrive
+    fn read_byte_blocking(&mut self) -> io::Result<u8> {
+        loop {
+            if let Some(byte) = self.read_byte()? {
+                return Ok(byte);
+            }
+            if self.script.is_empty() {
+                return Err(end_of_input());
+            }
+        }
+    }
+
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        self.output.lock().unwrap().push(byte);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The reader thread stops once stdin reached its end or failed
+fn stdin_closed() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "stdin is closed")
+}
+
+fn end_of_input() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "end of input")
+}
@@ -0,0 +1,85 @@
+use crate::cpu::{LC3Cpu, StepOutcome};
+use std::collections::VecDeque;
+
+/// Undo journal holding the outcome of the most recent instructions.
+/// The oldest entry is dropped once `capacity` instructions are recorded.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    capacity: usize,
+    entries: VecDeque<Entry>,
+}
+
+/// A recorded instruction with the state of the devices before it ran
+#[derive(Debug, Clone)]
+struct Entry {
+    outcome: StepOutcome,
+    devices: Vec<Vec<u8>>,
+}
+
+impl Journal {
+    pub fn new(capacity: usize) -> Self {
+        Journal {
+            capacity,
+            entries: VecDeque::with_capacity(capacity.min(4096)),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Recorded instructions, oldest first
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &StepOutcome> {
+        self.entries.iter().map(|entry| &entry.outcome)
+    }
+
+    pub(crate) fn record(&mut self, outcome: &StepOutcome, devices: Vec<Vec<u8>>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(Entry {
+            outcome: outcome.clone(),
+            devices,
+        });
+    }
+
+    /// Revert the most recent instruction on `cpu` and return its outcome
+    pub(crate) fn undo(&mut self, cpu: &mut LC3Cpu) -> Option<StepOutcome> {
+        let Entry { outcome, devices } = self.entries.pop_back()?;
+        // Later writes to the same address must be reverted first
+        for write in outcome.memory_written.iter().rev() {
+            cpu.restore_word(write.address, write.old);
+        }
+        for write in &outcome.registers_written {
+            cpu.registers[write.register] = write.old;
+        }
+        // The states were saved from these very devices
+        cpu.bus
+            .load_states(&devices)
+            .expect("devices reload their own state");
+        cpu.halted = false;
+        cpu.cycles = outcome.cycle;
+        Some(outcome)
+    }
+
+    /// The most recent instruction that wrote `address`
+    pub fn last_write(&self, address: u16) -> Option<&StepOutcome> {
+        self.entries().rev().find(|outcome| {
+            outcome
+                .memory_written
+                .iter()
+                .any(|write| write.address == address)
+        })
+    }
+}
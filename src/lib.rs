@@ -0,0 +1,30 @@
+//! Little Computer 3 VM written in Rust
+//! Read technical reference here: https://en.wikipedia.org/wiki/Little_Computer_3
+//! Instruction set architecture reference: https://www.jmeiners.com/lc3-vm/supplies/lc3-isa.pdf
+#![allow(clippy::upper_case_acronyms)]
+
+pub mod asm;
+mod constant;
+pub mod coverage;
+mod cpu;
+pub mod debugger;
+pub mod device;
+pub mod disasm;
+mod error;
+pub mod gdbstub;
+pub mod input;
+pub mod instruction;
+pub mod io;
+pub mod journal;
+pub mod profile;
+pub mod register;
+pub mod snapshot;
+pub mod terminal;
+pub mod trace;
+pub mod trap;
+mod vm;
+pub mod watch;
+
+pub use crate::cpu::{sign_extend, MemoryWrite, RegisterWrite, StepOutcome};
+pub use crate::error::VmError;
+pub use crate::vm::{read_image, StopReason, Vm};
@@ -1,233 +1,466 @@
-/// Little Computer 3 VM written in Rust
-/// Read technical reference here: https://en.wikipedia.org/wiki/Little_Computer_3Instruction set architecture reference: https://www.jmeiners.com/lc3-vm/supplies/lc3-isa.pdf
-mod constant;
-mod cpu;
-mod instruction;
-mod register;
-mod trap;
-
-use crate::constant::{IMMEDIATE_MODE, NEGATIVE_BIT, POSITIVE_BIT, REGISTER_MODE};
-use crate::cpu::LC3Cpu;
-use crate::instruction::LC3Instruction;
-use crate::register::{LC3CPURegister::*, LC3ConditionalFlags};
-use crate::trap::TrapRoutine;
-use std::fs::File;
-use std::io::BufReader;
-use std::process::abort;
-
-use byteorder::{BigEndian, ReadBytesExt};
-use structopt::StructOpt;
+use lc3_vm::coverage::Coverage;
+use lc3_vm::debugger::Debugger;
+use lc3_vm::gdbstub::GdbStub;
+use lc3_vm::io::SharedStdin;
+use lc3_vm::profile::Profiler;
+use lc3_vm::register::LC3CPURegister::{COND, PC};
+use lc3_vm::terminal::RawMode;
+use lc3_vm::trace::{self, TraceFormat, TraceRecord, Tracer};
+use lc3_vm::{asm, disasm, input, read_image, Vm, VmError};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use structopt::{clap, StructOpt};
 
 #[derive(StructOpt)]
 struct Cli {
+    #[structopt(subcommand)]
+    command: Option<Command>,
+
     /// The path to the file to read
     #[structopt(parse(from_os_str))]
-    path: std::path::PathBuf,
+    path: Option<PathBuf>,
+
+    /// Print the disassembly of the image instead of running it
+    #[structopt(long)]
+    print_asm: bool,
+
+    /// Start an interactive debugger instead of running the program.
+    /// Labels are read from the symbol table next to the image when there is one.
+    #[structopt(long)]
+    debug: bool,
+
+    /// Wait for a GDB client on this local TCP port and let it drive the program
+    #[structopt(long)]
+    gdb: Option<u16>,
+
+    /// Start execution at this address instead of the image origin (e.g. x3000)
+    #[structopt(long, parse(try_from_str = parse_address))]
+    entry: Option<u16>,
+
+    /// Write a record of every executed instruction to this file
+    #[structopt(long, parse(from_os_str))]
+    trace: Option<PathBuf>,
+
+    /// Format of the trace file: jsonl or binary. Only binary traces can be compared with
+    /// `trace diff`.
+    #[structopt(long, default_value = "jsonl")]
+    trace_format: TraceFormat,
+
+    /// Count executed instructions and print a hot-spot report to stderr when the program halts
+    #[structopt(long)]
+    profile: bool,
 
+    /// Write the profiled call stacks to this file in the folded format used by flamegraph tools
+    #[structopt(long, parse(from_os_str))]
+    profile_folded: Option<PathBuf>,
+
+    /// Write lcov coverage of the `.asm` source next to the image to this file
+    #[structopt(long, parse(from_os_str))]
+    coverage: Option<PathBuf>,
+
+    /// Write the `.asm` source next to the image annotated with execution counts to this file
+    #[structopt(long, parse(from_os_str))]
+    coverage_listing: Option<PathBuf>,
+
+    /// Restore the machine from this snapshot after loading the image
+    #[structopt(long, parse(from_os_str))]
+    load_state: Option<PathBuf>,
+
+    /// Write a snapshot of the machine to this file when the program stops before halting,
+    /// after --save-state-at or a fault. A halted program cannot be resumed and is not saved.
+    #[structopt(long, parse(from_os_str))]
+    save_state: Option<PathBuf>,
+
+    /// Stop the program once this many instructions have been executed and save its state,
+    /// resume it later with --load-state
+    #[structopt(long, requires = "save-state")]
+    save_state_at: Option<u64>,
+
+    /// Log every input byte with the cycle the program first saw it at to this file
+    #[structopt(long, parse(from_os_str))]
+    record_input: Option<PathBuf>,
+
+    /// Feed the program the input recorded in this file instead of reading the keyboard
+    #[structopt(long, parse(from_os_str))]
+    replay_input: Option<PathBuf>,
+
+    /// Leave the terminal in line-buffered, echoing mode instead of passing key presses to
+    /// the program as they are typed. Raw mode is only used when stdin is a terminal.
     #[structopt(long)]
-    print_asm: bool, // Future feature
+    no_raw: bool,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Assemble LC-3 source into an object image and a symbol table
+    Asm {
+        /// The assembly source file
+        #[structopt(parse(from_os_str))]
+        source: PathBuf,
+
+        /// Path of the object image, defaults to the source path with an .obj extension.
+        /// The symbol table is written next to it with a .sym extension.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+    /// Work with execution traces written by --trace
+    Trace(TraceCommand),
 }
 
-/// Read image from a provided input path
-fn load_image(cpu: &mut LC3Cpu) {
+#[derive(StructOpt)]
+enum TraceCommand {
+    /// Compare two binary traces, recorded with --trace-format binary, and report the first
+    /// instruction where they diverge
+    Diff {
+        #[structopt(parse(from_os_str))]
+        a: PathBuf,
+        #[structopt(parse(from_os_str))]
+        b: PathBuf,
+    },
+}
+
+/// Parse an address written in LC-3 (`x3000`, `#12288`) or Rust (`0x3000`) notation
+fn parse_address(src: &str) -> Result<u16, String> {
+    asm::parse_address(src).ok_or_else(|| format!("invalid address {}", src))
+}
+
+fn main() {
     let cli = Cli::from_args();
+    match &cli.command {
+        Some(Command::Asm { source, output }) => {
+            assemble(source, output.as_deref());
+            return;
+        }
+        Some(Command::Trace(TraceCommand::Diff { a, b })) => trace_diff(a, b),
+        None => {}
+    }
+    let Some(path) = &cli.path else {
+        clap::Error::with_description(
+            "the path of the image to run is required",
+            clap::ErrorKind::MissingRequiredArgument,
+        )
+        .exit();
+    };
+    if cli.print_asm {
+        print_asm(path);
+        return;
+    }
+    let mut vm = Vm::new();
+    if let Err(e) = vm.load(path) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+    if let Some(state_path) = &cli.load_state {
+        if let Err(e) = vm.load_state(state_path) {
+            eprintln!("error: {}: {}", state_path.display(), e);
+            std::process::exit(1);
+        }
+    }
+    if let Some(entry) = cli.entry {
+        vm.set_register(PC, entry);
+    }
+    if let Some(log_path) = &cli.replay_input {
+        let events = File::open(log_path).and_then(|f| input::read_log(BufReader::new(f)));
+        match events {
+            Ok(events) => vm.replay_input(events),
+            Err(e) => {
+                eprintln!("error: {}: {}", log_path.display(), e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if cli.record_input.is_some() {
+        vm.record_input();
+    }
 
-    let f = File::open(cli.path).expect("couldn't open file");
-    let mut f = BufReader::new(f);
-
-    // Note how we're using `read_u16` _and_ BigEndian to read the roms file.
-    // Most modern computers are little-endian (LE) but LC3 programs are big-endian (BE)
-    let base_address = f.read_u16::<BigEndian>().expect("error");
-
-    // Here we're loading the program in memory
-    // First 16 bits tell the LC3 which address to load the program
-    let mut address = base_address;
-    loop {
-        match f.read_u16::<BigEndian>() {
-            Ok(instruction) => {
-                cpu.mem_write(address, instruction);
-                address += 1;
+    if cli.debug {
+        let mut debugger = Debugger::new(vm);
+        if let Ok(text) = fs::read_to_string(path.with_extension("sym")) {
+            debugger.set_symbols(asm::parse_symbols(&text));
+        }
+        // The program being debugged reads the same stdin as the command prompt
+        if let Err(e) = debugger.repl(SharedStdin::new(), io::stdout()) {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(port) = cli.gdb {
+        let result = TcpListener::bind(("127.0.0.1", port)).and_then(|listener| {
+            eprintln!("waiting for gdb on {}", listener.local_addr()?);
+            GdbStub::new(vm).listen(&listener)
+        });
+        if let Err(e) = result {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Open the trace file and assemble the coverage source before switching the terminal to
+    // raw mode: exiting on an error skips the guard and would leave the terminal raw
+    let coverage_requested = cli.coverage.is_some() || cli.coverage_listing.is_some();
+    let tracer = cli.trace.as_ref().map(|trace_path| {
+        File::create(trace_path)
+            .and_then(|f| Tracer::new(BufWriter::new(f), cli.trace_format))
+            .unwrap_or_else(|e| {
+                eprintln!("error: {}: {}", trace_path.display(), e);
+                std::process::exit(1);
+            })
+    });
+    let source = coverage_requested.then(|| coverage_source(path));
+
+    // Interactive programs want key presses immediately, the terminal is restored once the
+    // program stops
+    let raw_mode = if cli.no_raw {
+        None
+    } else {
+        RawMode::enable().unwrap_or_else(|e| {
+            eprintln!("error: cannot switch the terminal to raw mode: {}", e);
+            std::process::exit(1);
+        })
+    };
+    let stop_at = cli.save_state_at.unwrap_or(u64::MAX);
+    if tracer.is_some() || cli.profile || cli.profile_folded.is_some() || coverage_requested {
+        let mut instruments = Instruments {
+            tracer,
+            profiler: (cli.profile || cli.profile_folded.is_some()).then(Profiler::new),
+            coverage: coverage_requested.then(Coverage::new),
+        };
+        let result = instruments.run(&mut vm, stop_at);
+        drop(raw_mode);
+        if let Some(profiler) = &instruments.profiler {
+            let labels = symbol_labels(path);
+            if cli.profile {
+                eprint!("{}", profiler.report(&labels));
             }
-            Err(e) => {
-                if e.kind() == std::io::ErrorKind::UnexpectedEof {
-                    println!("OK")
-                } else {
-                    println!("failed: {}", e);
+            if let Some(folded_path) = &cli.profile_folded {
+                write_report(folded_path, &profiler.folded(&labels));
+            }
+        }
+        if let (Some(coverage), Some((source_path, text, assembly))) =
+            (&instruments.coverage, &source)
+        {
+            if let Some(lcov_path) = &cli.coverage {
+                let name = source_path.display().to_string();
+                write_report(lcov_path, &coverage.lcov(&name, assembly));
+            }
+            if let Some(listing_path) = &cli.coverage_listing {
+                write_report(listing_path, &coverage.annotate(text, assembly));
+            }
+        }
+        write_session(&vm, &cli);
+        if let Err(e) = result {
+            eprintln!("error: {} (PC = {:#06x})", e, vm.register(PC));
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // User console
+    // A program that reaches HALT exits with 0, non-zero is reserved for faults
+    let result = vm.run_until(stop_at);
+    drop(raw_mode);
+    write_session(&vm, &cli);
+    if let Err(e) = result {
+        eprintln!("error: {} (PC = {:#06x})", e, vm.register(PC));
+        std::process::exit(1);
+    }
+}
+
+/// Observers fed with every instruction executed by the instrumented run loop
+struct Instruments {
+    tracer: Option<Tracer<BufWriter<File>>>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+}
+
+impl Instruments {
+    /// Run until HALT or until `stop_at` instructions were executed, recording every executed
+    /// instruction. The trace is flushed before a fault or a failed trace write is reported so
+    /// it ends with the last instruction that completed, the profile and coverage are kept
+    /// either way.
+    fn run(&mut self, vm: &mut Vm, stop_at: u64) -> Result<(), VmError> {
+        let result = loop {
+            if vm.cycles() >= stop_at {
+                break Ok(());
+            }
+            let outcome = match vm.step() {
+                Ok(outcome) => outcome,
+                Err(e) => break Err(e),
+            };
+            if let Some(tracer) = &mut self.tracer {
+                if let Err(e) = tracer.record(&TraceRecord::new(&outcome, vm.register(COND))) {
+                    break Err(e.into());
                 }
-                break;
             }
+            if let Some(profiler) = &mut self.profiler {
+                profiler.record(&outcome);
+            }
+            if let Some(coverage) = &mut self.coverage {
+                coverage.record(&outcome);
+            }
+            if outcome.halted {
+                break Ok(());
+            }
+        };
+        let finished = match self.tracer.take() {
+            Some(tracer) => tracer.finish().map(drop),
+            None => Ok(()),
+        };
+        result.and(finished.map_err(Into::into))
+    }
+}
+
+/// Read and assemble the `.asm` source next to the image, coverage is reported against it.
+/// The source must assemble to the very image being run, lines would be misattributed
+/// otherwise.
+fn coverage_source(path: &Path) -> (PathBuf, String, asm::Assembly) {
+    let source_path = path.with_extension("asm");
+    let text = fs::read_to_string(&source_path).unwrap_or_else(|e| {
+        eprintln!("error: {}: {}", source_path.display(), e);
+        std::process::exit(1);
+    });
+    let assembly = asm::assemble(&text).unwrap_or_else(|e| {
+        eprintln!("{}:{}", source_path.display(), e);
+        std::process::exit(1);
+    });
+    let image = File::open(path)
+        .map_err(Into::into)
+        .and_then(|f| read_image(BufReader::new(f)));
+    match image {
+        Ok((origin, words)) if origin == assembly.origin && words == assembly.words => {}
+        Ok(_) => {
+            eprintln!(
+                "error: {} does not assemble to {}, reassemble it",
+                source_path.display(),
+                path.display()
+            );
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
         }
     }
+    (source_path, text, assembly)
 }
 
-/// Function to sign extend a 16 bit integer
-/// - x: unsigned 16-bit integer
-/// - bit_count: number of significant bits in `x`. How many bits of `x` should be considered when performing the sign extension
-/// e.g. x = 1100100 with the bit_count = 6 => 1 is the right most index in the bit set.
-pub fn sign_extend(mut x: u16, bit_count: i32) -> u16 {
-    // Get the rightmost bit index in the bit set and check if the value of the bit is 1 (negative) or 0 (positive)
-    // - 0xFFFF in hexadecimal = 1111 1111 1111 1111 in roms
-    // - If the sign is negative (0) => do OR operation to bit mask with `bit_count` most significant bits set to 1
-    if ((x >> (bit_count - 1)) & 1) == NEGATIVE_BIT {
-        x |= 0xFFFF << bit_count;
-    }
-    x
+/// Write the input log and the snapshot once the program stopped, whether it halted or
+/// faulted. A halted machine is not saved, restoring it could only fail.
+fn write_session(vm: &Vm, cli: &Cli) {
+    if let (Some(path), Some(events)) = (&cli.record_input, vm.recorded_input()) {
+        let result = File::create(path).and_then(|f| input::write_log(events, BufWriter::new(f)));
+        if let Err(e) = result {
+            eprintln!("error: {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+    if let Some(path) = &cli.save_state {
+        if vm.is_halted() {
+            eprintln!(
+                "error: {}: not saved, the program halted and cannot be resumed",
+                path.display()
+            );
+            std::process::exit(1);
+        }
+        if let Err(e) = vm.save_state(path) {
+            eprintln!("error: {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
 }
 
-fn main() {
-    let mut cpu = LC3Cpu::default();
-    // Conditional flag always requires a value, set a zero flag by default
-    cpu.registers[COND as usize] = LC3ConditionalFlags::ZRO as u16;
+fn write_report(path: &Path, contents: &str) {
+    if let Err(e) = fs::write(path, contents) {
+        eprintln!("error: {}: {}", path.display(), e);
+        std::process::exit(1);
+    }
+}
 
-    // Set the PC to starting position => 0x3000 is the default
-    cpu.registers[PC as usize] = constant::PROGRAM_COUNTER_START;
+/// Labels from the symbol table next to the image, empty when there is none
+fn symbol_labels(path: &Path) -> disasm::Labels {
+    fs::read_to_string(path.with_extension("sym"))
+        .map(|text| {
+            asm::parse_symbols(&text)
+                .into_iter()
+                .map(|(name, address)| (address, name))
+                .collect()
+        })
+        .unwrap_or_default()
+}
 
-    // User console
-    load_image(&mut cpu);
-
-    loop {
-        cpu.registers[PC as usize] += 1;
-        let instruction: u16 = cpu.mem_read(cpu.registers[PC as usize]);
-        match LC3Instruction::from_bytes(instruction) {
-            Some(opcode) => {
-                println!("{:?}", opcode);
-                match opcode {
-                    LC3Instruction::ADD => {
-                        let dr = (instruction >> 9) & 0x7;
-                        let sr1 = (instruction >> 6) & 0x7;
-                        let mode = (instruction >> 5) & 0x1;
-                        match mode {
-                            IMMEDIATE_MODE => {
-                                let imm5 = sign_extend(instruction & 0x1F, 5);
-                                cpu.registers[dr as usize] = cpu.registers[sr1 as usize] + imm5;
-                            }
-                            REGISTER_MODE => {
-                                let sr2: u16 = instruction & 0x7;
-                                let val: u32 = (cpu.registers[sr1 as usize]
-                                    + cpu.registers[sr2 as usize])
-                                    as u32;
-                                cpu.registers[dr as usize] = val as u16;
-                            }
-                            _ => panic!("Invalid mode"),
-                        }
-                        cpu.update_flags(dr);
-                    } /* add  */
-                    LC3Instruction::AND => {
-                        let dr = (instruction >> 9) & 0x7;
-                        let sr1 = (instruction >> 6) & 0x7;
-                        let mode = (instruction >> 5) & 0x1;
-                        match mode {
-                            IMMEDIATE_MODE => {
-                                let imm5 = sign_extend(instruction & 0x1F, 5);
-                                cpu.registers[dr as usize] = cpu.registers[sr1 as usize] & imm5;
-                            }
-                            REGISTER_MODE => {
-                                let sr2: u16 = instruction & 0x7;
-                                cpu.registers[dr as usize] =
-                                    cpu.registers[sr1 as usize] & cpu.registers[sr2 as usize];
-                            }
-                            _ => panic!("Invalid mode"),
-                        }
-                        cpu.update_flags(dr);
-                    } /* bitwise and */
-                    LC3Instruction::BR => {
-                        // If any of the condition codes tested is set, the program branches to the location
-                        // specified by adding the sign-extended pc_offset_9 field to the incremented PC.
-                        let cond_flag = (instruction >> 9) & 0x7;
-                        let pc_offset_9 = sign_extend(instruction & 0x1FF, 9);
-                        if cond_flag & cpu.registers[COND as usize] != POSITIVE_BIT {
-                            cpu.registers[PC as usize] += pc_offset_9;
-                        }
-                    } /* branch */
-                    LC3Instruction::JMP => {
-                        // The program unconditionally jumps to the location specified by the contents of the base register
-                        let base_register = (instruction >> 6) & 0x7;
-                        cpu.registers[PC as usize] = cpu.registers[base_register as usize];
-                    } /* jump */
-                    LC3Instruction::LD => {
-                        let dr = (instruction >> 9) & 0x7;
-                        let pc_offset_9 = sign_extend(instruction & 0x1FF, 9);
-                        let val: u32 = (cpu.registers[PC as usize] + pc_offset_9) as u32;
-                        cpu.registers[dr as usize] = cpu.mem_read(val as u16);
-                        cpu.update_flags(dr);
-                    } /* load */
-                    LC3Instruction::ST => {
-                        let sr = (instruction >> 9) & 0x7;
-                        let pc_offset = sign_extend(instruction & 0x1FF, 9);
-                        cpu.mem_write(
-                            cpu.registers[PC as usize] + pc_offset,
-                            cpu.registers[sr as usize],
-                        );
-                    } /* store */
-                    LC3Instruction::JSR => {
-                        let mode = (instruction >> 11) & 0x1;
-                        cpu.registers[R7 as usize] = cpu.registers[PC as usize];
-                        match mode {
-                            IMMEDIATE_MODE => {
-                                /* JSR */
-                                let pc_offset_11 = (sign_extend(instruction & 0x7FF, 11)) as u32;
-                                cpu.registers[PC as usize] += pc_offset_11 as u16;
-                            }
-                            REGISTER_MODE => {
-                                /* JSRR */
-                                let base_r = (instruction >> 6) & 0x7;
-                                cpu.registers[PC as usize] = cpu.registers[base_r as usize];
-                            }
-                            _ => panic!("Invalid mode"),
-                        }
-                    } /* jump register */
-                    LC3Instruction::LDR => {
-                        let dr = (instruction >> 9) & 0x7;
-                        let base_r = (instruction >> 6) & 0x7;
-                        let offset_6 = sign_extend(instruction & 0x3F, 6);
-                        let val: u32 =
-                            cpu.mem_read(cpu.registers[base_r as usize] + offset_6) as u32;
-                        cpu.registers[dr as usize] = val as u16;
-                        cpu.update_flags(dr);
-                    } /* load register */
-                    LC3Instruction::STR => {
-                        let sr = (instruction >> 9) & 0x7;
-                        let base_r = (instruction >> 6) & 0x7;
-                        let offset_6 = sign_extend(instruction & 0x3F, 6);
-                        let val = (cpu.registers[base_r as usize] + offset_6) as u32;
-                        cpu.mem_write(val as u16, cpu.registers[sr as usize]);
-                    } /* store register */
-                    LC3Instruction::NOT => {
-                        let dr = (instruction >> 9) & 0x7;
-                        let sr = (instruction >> 6) & 0x7;
-                        cpu.registers[dr as usize] = !cpu.registers[sr as usize];
-                        cpu.update_flags(dr);
-                    } /* bitwise not */
-                    LC3Instruction::LDI => {
-                        let dr = (instruction >> 9) & 0x7;
-                        let pc_offset_9 = sign_extend(instruction & 0x1FF, 9);
-                        let updated_pc_data =
-                            cpu.mem_read(cpu.registers[PC as usize] + pc_offset_9);
-                        cpu.registers[dr as usize] = cpu.mem_read(updated_pc_data);
-                        cpu.update_flags(dr);
-                    } /* load indirect */
-                    LC3Instruction::STI => {
-                        let sr = (instruction >> 9) & 0x7;
-                        let pc_offset_9 = sign_extend(instruction & 0x1FF, 9);
-                        let updated_pc_data =
-                            cpu.mem_read(cpu.registers[PC as usize] + pc_offset_9);
-                        cpu.mem_write(updated_pc_data, cpu.registers[sr as usize]);
-                    } /* store indirect */
-                    LC3Instruction::LEA => {
-                        let dr = (instruction >> 9) & 0x7;
-                        let pc_offset_9 = sign_extend(instruction & 0x1FF, 9);
-                        cpu.registers[dr as usize] = cpu.registers[PC as usize] + pc_offset_9;
-                        cpu.update_flags(dr);
-                    } /* load effective address */
-                    LC3Instruction::RTI | LC3Instruction::RES => {
-                        abort();
-                    }
-                    LC3Instruction::TRAP => {
-                        TrapRoutine::execute(&mut cpu, instruction & 0xFF);
-                    } /* execute trap */
-                }
-            }
-            None => panic!("Invalid instruction opcode"),
+/// Print the first record where two traces diverge, exits with 1 when they differ
+fn trace_diff(a: &Path, b: &Path) -> ! {
+    let read = |path: &Path| {
+        File::open(path)
+            .and_then(|f| trace::read_trace(BufReader::new(f)))
+            .unwrap_or_else(|e| {
+                eprintln!("error: {}: {}", path.display(), e);
+                std::process::exit(2);
+            })
+    };
+    let (a_records, b_records) = (read(a), read(b));
+    let Some(index) = trace::first_difference(&a_records, &b_records) else {
+        println!("traces are identical ({} instructions)", a_records.len());
+        std::process::exit(0);
+    };
+    println!("traces diverge at instruction {}", index);
+    for (path, records) in [(a, &a_records), (b, &b_records)] {
+        match records.get(index) {
+            Some(record) => println!("{}: {}", path.display(), record),
+            None => println!("{}: <end of trace>", path.display()),
         }
     }
+    std::process::exit(1);
+}
+
+/// Dump the image with addresses, raw hex and mnemonics
+fn print_asm(path: &Path) {
+    let image = File::open(path)
+        .map_err(Into::into)
+        .and_then(|f| read_image(BufReader::new(f)));
+    match image {
+        Ok((origin, words)) => print!("{}", disasm::listing(origin, &words)),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Assemble `source` into an object image and a symbol table next to it
+fn assemble(source: &Path, output: Option<&Path>) {
+    let text = fs::read_to_string(source).unwrap_or_else(|e| {
+        eprintln!("error: {}: {}", source.display(), e);
+        std::process::exit(1);
+    });
+    let assembly = asm::assemble(&text).unwrap_or_else(|e| {
+        eprintln!("{}:{}", source.display(), e);
+        std::process::exit(1);
+    });
+
+    let obj_path = output
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| source.with_extension("obj"));
+    let sym_path = obj_path.with_extension("sym");
+    let result = File::create(&obj_path)
+        .and_then(|f| {
+            let mut writer = BufWriter::new(f);
+            assembly.write_obj(&mut writer)?;
+            writer.flush()
+        })
+        .and_then(|_| File::create(&sym_path))
+        .and_then(|f| {
+            let mut writer = BufWriter::new(f);
+            assembly.write_sym(&mut writer)?;
+            writer.flush()
+        });
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
 }
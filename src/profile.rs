@@ -0,0 +1,203 @@
+use crate::cpu::StepOutcome;
+use crate::disasm::{self, Labels};
+use crate::instruction::{Instruction, LC3Instruction};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+/// Number of addresses listed in the hot-spot report
+const HOT_SPOTS: usize = 20;
+
+/// A subroutine activation, `entry` names the subroutine and `return_address` is the value
+/// JSR/JSRR saved in R7
+#[derive(Clone, Copy, Debug)]
+struct Frame {
+    entry: u16,
+    return_address: u16,
+}
+
+/// Executions of a single address, `word` is the last instruction seen there
+#[derive(Clone, Copy, Debug, Default)]
+struct AddressCount {
+    count: u64,
+    word: u16,
+}
+
+/// Cycles attributed to a subroutine
+#[derive(Clone, Copy, Debug, Default)]
+struct SubroutineCount {
+    /// Cycles spent in the subroutine and everything it called
+    inclusive: u64,
+    /// Cycles spent in the subroutine itself
+    exclusive: u64,
+    calls: u64,
+}
+
+/// Counts executed instructions per address, per opcode and per subroutine.
+/// Call stacks are followed through JSR/JSRR and the `RET` that jumps back to the saved R7.
+#[derive(Debug, Default)]
+pub struct Profiler {
+    total: u64,
+    addresses: HashMap<u16, AddressCount>,
+    opcodes: HashMap<LC3Instruction, u64>,
+    subroutines: HashMap<u16, SubroutineCount>,
+    /// Current call stack, the first frame is the code the program started in
+    stack: Vec<Frame>,
+    /// Cycles per call stack, keyed by the entry addresses from the outermost frame
+    stacks: HashMap<Vec<u16>, u64>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for one executed instruction
+    pub fn record(&mut self, outcome: &StepOutcome) {
+        if self.stack.is_empty() {
+            self.stack.push(Frame {
+                entry: outcome.pc_before,
+                return_address: outcome.pc_before,
+            });
+        }
+        self.total += 1;
+        let address = self.addresses.entry(outcome.pc_before).or_default();
+        address.count += 1;
+        address.word = outcome.instruction;
+        *self.opcodes.entry(outcome.opcode).or_default() += 1;
+
+        // The cycle belongs to every subroutine on the stack, recursive ones count once
+        let entries: Vec<u16> = self.stack.iter().map(|frame| frame.entry).collect();
+        for (depth, &entry) in entries.iter().enumerate() {
+            let subroutine = self.subroutines.entry(entry).or_default();
+            if !entries[..depth].contains(&entry) {
+                subroutine.inclusive += 1;
+            }
+            if depth == entries.len() - 1 {
+                subroutine.exclusive += 1;
+            }
+        }
+        *self.stacks.entry(entries).or_default() += 1;
+
+        match Instruction::decode(outcome.instruction) {
+            Instruction::Jsr { .. } | Instruction::Jsrr { .. } => {
+                self.stack.push(Frame {
+                    entry: outcome.pc_after,
+                    return_address: outcome.pc_before.wrapping_add(1),
+                });
+                self.subroutines.entry(outcome.pc_after).or_default().calls += 1;
+            }
+            Instruction::Jmp { .. } => {
+                // A return may skip frames when a subroutine unwinds past its caller
+                if let Some(depth) = self
+                    .stack
+                    .iter()
+                    .skip(1)
+                    .rposition(|frame| frame.return_address == outcome.pc_after)
+                {
+                    self.stack.truncate(depth + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Number of instructions recorded
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Times the instruction at `address` was executed
+    pub fn count(&self, address: u16) -> u64 {
+        self.addresses
+            .get(&address)
+            .map_or(0, |address| address.count)
+    }
+
+    /// Times an instruction with `opcode` was executed
+    pub fn opcode_count(&self, opcode: LC3Instruction) -> u64 {
+        self.opcodes.get(&opcode).copied().unwrap_or(0)
+    }
+
+    /// Hot spots, opcode mix and subroutines sorted by the number of cycles spent in them
+    pub fn report(&self, labels: &Labels) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{} instructions executed", self.total);
+
+        let mut addresses: Vec<_> = self.addresses.iter().collect();
+        addresses.sort_by_key(|(&address, count)| (std::cmp::Reverse(count.count), address));
+        let _ = writeln!(out, "\nhot spots:");
+        for (&address, count) in addresses.into_iter().take(HOT_SPOTS) {
+            let _ = writeln!(
+                out,
+                "{:>10} {:>6}  x{:04X}  {:<8}{}",
+                count.count,
+                self.percent(count.count),
+                address,
+                labels.get(&address).map_or("", String::as_str),
+                disasm::disassemble_at(count.word, address, labels)
+            );
+        }
+
+        let mut opcodes: Vec<_> = self.opcodes.iter().collect();
+        opcodes.sort_by_key(|(opcode, &count)| (std::cmp::Reverse(count), format!("{:?}", opcode)));
+        let _ = writeln!(out, "\nopcodes:");
+        for (opcode, &count) in opcodes {
+            let _ = writeln!(
+                out,
+                "{:>10} {:>6}  {:?}",
+                count,
+                self.percent(count),
+                opcode
+            );
+        }
+
+        let mut subroutines: Vec<_> = self.subroutines.iter().collect();
+        subroutines.sort_by_key(|(&entry, count)| (std::cmp::Reverse(count.inclusive), entry));
+        let _ = writeln!(out, "\nsubroutines:");
+        let _ = writeln!(
+            out,
+            "{:>10} {:>6} {:>10} {:>6} {:>8}  name",
+            "inclusive", "", "self", "", "calls"
+        );
+        for (&entry, count) in subroutines {
+            let _ = writeln!(
+                out,
+                "{:>10} {:>6} {:>10} {:>6} {:>8}  {}",
+                count.inclusive,
+                self.percent(count.inclusive),
+                count.exclusive,
+                self.percent(count.exclusive),
+                count.calls,
+                name(entry, labels)
+            );
+        }
+        out
+    }
+
+    /// Call stacks in the folded format read by flamegraph tools: frames separated by `;`
+    /// followed by the number of cycles spent in that stack
+    pub fn folded(&self, labels: &Labels) -> String {
+        let mut lines: Vec<String> = self
+            .stacks
+            .iter()
+            .map(|(stack, count)| {
+                let frames: Vec<String> = stack.iter().map(|&entry| name(entry, labels)).collect();
+                format!("{} {}", frames.join(";"), count)
+            })
+            .collect();
+        lines.sort();
+        lines.into_iter().map(|line| line + "\n").collect()
+    }
+
+    fn percent(&self, count: u64) -> String {
+        format!("{:.1}%", count as f64 * 100.0 / self.total.max(1) as f64)
+    }
+}
+
+/// Label of a subroutine entry point, or its address when it has none
+fn name(entry: u16, labels: &Labels) -> String {
+    labels
+        .get(&entry)
+        .cloned()
+        .unwrap_or_else(|| format!("x{:04X}", entry))
+}
@@ -1,9 +1,12 @@
 /**
 LC-3 has 10 total registers, each of which is 16 bits.
 Most of them are general purpose, but a few have designated roles.
+The processor status register and the stack pointer saved for the inactive privilege mode
+complete the register file.
  **/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[allow(non_camel_case_types)]
-pub(crate) enum LC3CPURegister {
+pub enum LC3CPURegister {
     /** General purpose register (R0 - R7) **/
     R0 = 0x0,
     R1 = 0x1,
@@ -15,23 +18,73 @@ pub(crate) enum LC3CPURegister {
     R7 = 0x7,
     /** Program counter register **/
     PC = 0x8,
-    /** Conditional register **/
+    /** Conditional register, mirrors the N/Z/P bits of PSR **/
     COND = 0x9,
+    /** Processor status register: privilege mode (bit 15), priority (bits 10 - 8) and N/Z/P **/
+    PSR = 0xA,
+    /** User stack pointer, saved while R6 holds the supervisor stack pointer **/
+    USP = 0xB,
+    /** Supervisor stack pointer, saved while R6 holds the user stack pointer **/
+    SSP = 0xC,
+}
+
+impl LC3CPURegister {
+    /// Every register in register file order
+    pub const ALL: [LC3CPURegister; 13] = [
+        LC3CPURegister::R0,
+        LC3CPURegister::R1,
+        LC3CPURegister::R2,
+        LC3CPURegister::R3,
+        LC3CPURegister::R4,
+        LC3CPURegister::R5,
+        LC3CPURegister::R6,
+        LC3CPURegister::R7,
+        LC3CPURegister::PC,
+        LC3CPURegister::COND,
+        LC3CPURegister::PSR,
+        LC3CPURegister::USP,
+        LC3CPURegister::SSP,
+    ];
+
+    /// Look a register up by its name (`R0` - `R7`, `PC`, `COND`, `PSR`, `USP`, `SSP`),
+    /// ignoring case
+    pub fn from_name(name: &str) -> Option<Self> {
+        LC3CPURegister::ALL
+            .into_iter()
+            .find(|register| format!("{:?}", register).eq_ignore_ascii_case(name))
+    }
 }
 
 /// Memory Mapped Register: Some special registers are not accessible from the normal register table.
 /// Instead, a special address is reserved for them in memory.
-pub(crate) enum MemoryMappedRegister {
+pub enum MemoryMappedRegister {
     KBSR = 0xFE00, /* keyboard status */
     KBDR = 0xFE02, /* keyboard data */
+    DSR = 0xFE04,  /* display status */
+    DDR = 0xFE06,  /* display data */
+    PSR = 0xFFFC,  /* processor status */
+    MCR = 0xFFFE,  /* machine control */
 }
 
 /** The LC-3 uses only 3 condition flags which indicate the sign of the previous calculation.
 Why are we storing 1-2-4 instead of 1-2-3 ? Because the conditional flags are represented in a bit set format `nzp` not the index like register. Hence, 1 - 2 -4 => 111 => Three states: nz1 - n1p - 1zp
 **/
 #[allow(non_camel_case_types)]
-pub(crate) enum LC3ConditionalFlags {
+pub enum LC3ConditionalFlags {
     POS = 1 << 0, /* P */
     ZRO = 1 << 1, /* Z */
     NEG = 1 << 2, /* N */
 }
+
+/// Condition codes as `n`, `z` or `p`
+pub(crate) fn condition_codes(cond: u16) -> &'static str {
+    if cond == LC3ConditionalFlags::NEG as u16 {
+        "n"
+    } else if cond == LC3ConditionalFlags::ZRO as u16 {
+        "z"
+    } else if cond == LC3ConditionalFlags::POS as u16 {
+        "p"
+    } else {
+        "?"
+    }
+}
@@ -0,0 +1,111 @@
+use crate::constant;
+use crate::error::VmError;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, Read, Write};
+
+/// First bytes of a snapshot file, followed by the format version
+const MAGIC: &[u8; 4] = b"LC3S";
+const VERSION: u16 = 1;
+
+/// Complete machine state: registers, memory, the state of every device on the I/O page, the
+/// halted flag and the cycle counter. Debugging aids such as
+/// watchpoints and the undo journal are not part of the machine and are not saved.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    pub registers: [u16; constant::CPU_REGISTER_COUNT],
+    /// All 65,536 memory words
+    pub memory: Vec<u16>,
+    pub halted: bool,
+    pub cycles: u64,
+    /// State saved by each device, in the order the devices were attached
+    pub devices: Vec<Vec<u8>>,
+}
+
+impl Snapshot {
+    /// Write the snapshot in the versioned big-endian snapshot format
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_u16::<BigEndian>(VERSION)?;
+        for &register in &self.registers {
+            writer.write_u16::<BigEndian>(register)?;
+        }
+        writer.write_u8(self.halted as u8)?;
+        writer.write_u64::<BigEndian>(self.cycles)?;
+        for &word in &self.memory {
+            writer.write_u16::<BigEndian>(word)?;
+        }
+        write_len(&mut writer, self.devices.len())?;
+        for state in &self.devices {
+            write_len(&mut writer, state.len())?;
+            writer.write_all(state)?;
+        }
+        writer.flush()
+    }
+
+    /// Read a snapshot written by [`Snapshot::write`]
+    pub fn read<R: Read>(mut reader: R) -> Result<Self, VmError> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(VmError::InvalidSnapshot("not a snapshot file".to_string()));
+        }
+        let version = reader.read_u16::<BigEndian>()?;
+        if version != VERSION {
+            return Err(VmError::InvalidSnapshot(format!(
+                "unsupported snapshot version {}",
+                version
+            )));
+        }
+        let mut registers = [0; constant::CPU_REGISTER_COUNT];
+        reader.read_u16_into::<BigEndian>(&mut registers)?;
+        let halted = match reader.read_u8()? {
+            0 => false,
+            1 => true,
+            flag => {
+                return Err(VmError::InvalidSnapshot(format!(
+                    "invalid halted flag {}",
+                    flag
+                )))
+            }
+        };
+        let cycles = reader.read_u64::<BigEndian>()?;
+        let mut memory = vec![0; constant::MEMORY_MAX];
+        reader.read_u16_into::<BigEndian>(&mut memory)?;
+        let count = read_len(&mut reader)?;
+        let mut devices = Vec::new();
+        for _ in 0..count {
+            let len = read_len(&mut reader)?;
+            // Read through `take` so a corrupt length cannot allocate gigabytes up front
+            let mut state = Vec::new();
+            (&mut reader).take(len as u64).read_to_end(&mut state)?;
+            if state.len() != len {
+                return Err(VmError::InvalidSnapshot(
+                    "truncated device state".to_string(),
+                ));
+            }
+            devices.push(state);
+        }
+        Ok(Snapshot {
+            registers,
+            memory,
+            halted,
+            cycles,
+            devices,
+        })
+    }
+}
+
+fn read_len<R: Read>(reader: &mut R) -> io::Result<usize> {
+    Ok(reader.read_u32::<BigEndian>()? as usize)
+}
+
+/// Lengths are written as 32 bits, larger ones cannot be represented
+fn write_len<W: Write>(writer: &mut W, len: usize) -> io::Result<()> {
+    let len = u32::try_from(len).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("device state of {} bytes is too large for a snapshot", len),
+        )
+    })?;
+    writer.write_u32::<BigEndian>(len)
+}
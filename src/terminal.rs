@@ -0,0 +1,99 @@
+use std::io;
+
+/// Keeps stdin in non-canonical, non-echoing mode so key presses reach the program immediately
+/// and are not echoed, like the keyboard of a real LC-3. The original terminal settings are
+/// restored when the guard is dropped, when the process panics and when it is interrupted
+/// with Ctrl-C.
+pub struct RawMode {
+    #[cfg(unix)]
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::RawMode;
+    use std::io;
+    use std::sync::OnceLock;
+
+    /// Settings to put back from the panic hook and the SIGINT handler
+    static ORIGINAL: OnceLock<libc::termios> = OnceLock::new();
+
+    pub(super) fn enable() -> io::Result<Option<RawMode>> {
+        // SAFETY: isatty, tcgetattr and tcsetattr only access the termios value we own
+        unsafe {
+            if libc::isatty(libc::STDIN_FILENO) == 0 {
+                return Ok(None);
+            }
+            let mut original: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if ORIGINAL.set(original).is_ok() {
+                install_handlers();
+            }
+            let mut raw = original;
+            // Keep ISIG so Ctrl-C still interrupts the program
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Some(RawMode { original }))
+        }
+    }
+
+    pub(super) fn restore(original: &libc::termios) {
+        // SAFETY: tcsetattr is async-signal-safe and only reads `original`
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original);
+        }
+    }
+
+    fn install_handlers() {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if let Some(original) = ORIGINAL.get() {
+                restore(original);
+            }
+            previous(info);
+        }));
+        // SAFETY: the handler only calls async-signal-safe functions
+        unsafe {
+            libc::signal(
+                libc::SIGINT,
+                on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+
+    /// Restore the terminal, then die from SIGINT as if no handler had been installed
+    extern "C" fn on_interrupt(signal: libc::c_int) {
+        if let Some(original) = ORIGINAL.get() {
+            restore(original);
+        }
+        // SAFETY: signal and raise are async-signal-safe
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    }
+}
+
+impl RawMode {
+    /// Switch stdin to raw mode, `None` when stdin is not a terminal (e.g. input is piped) or
+    /// on platforms without termios
+    pub fn enable() -> io::Result<Option<RawMode>> {
+        #[cfg(unix)]
+        return unix::enable();
+        #[cfg(not(unix))]
+        return Ok(None);
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        unix::restore(&self.original);
+    }
+}
@@ -0,0 +1,272 @@
+use crate::cpu::{MemoryWrite, RegisterWrite, StepOutcome};
+use crate::disasm::{self, Labels};
+use crate::register::{condition_codes as flags, LC3CPURegister};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+/// First bytes of a binary trace, followed by the format version
+const BINARY_MAGIC: &[u8; 4] = b"LC3T";
+const BINARY_VERSION: u8 = 1;
+
+/// How trace records are written
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// One JSON object per line
+    Jsonl,
+    /// Compact big-endian records after a `LC3T` header
+    Binary,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" => Ok(TraceFormat::Jsonl),
+            "binary" => Ok(TraceFormat::Binary),
+            _ => Err(format!(
+                "unknown trace format {}, expected jsonl or binary",
+                s
+            )),
+        }
+    }
+}
+
+/// One executed instruction as it appears in a trace
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub cycle: u64,
+    /// Address the instruction was fetched from
+    pub pc: u16,
+    /// Raw instruction word
+    pub word: u16,
+    /// Condition codes after the instruction executed
+    pub cond: u16,
+    pub registers: Vec<RegisterWrite>,
+    pub memory: Vec<MemoryWrite>,
+}
+
+impl TraceRecord {
+    /// Build a record from a step outcome, `cond` is the COND register after the step
+    pub fn new(outcome: &StepOutcome, cond: u16) -> Self {
+        TraceRecord {
+            cycle: outcome.cycle,
+            pc: outcome.pc_before,
+            word: outcome.instruction,
+            cond,
+            registers: outcome.registers_written.clone(),
+            memory: outcome.memory_written.clone(),
+        }
+    }
+
+    pub fn disassembly(&self) -> String {
+        disasm::disassemble_at(self.word, self.pc, &Labels::new())
+    }
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{} x{:04X}  x{:04X}  {:<20} {}",
+            self.cycle,
+            self.pc,
+            self.word,
+            self.disassembly(),
+            flags(self.cond)
+        )?;
+        for write in &self.registers {
+            write!(
+                f,
+                " {}=x{:04X}->x{:04X}",
+                register_name(write.register),
+                write.old,
+                write.new
+            )?;
+        }
+        for write in &self.memory {
+            write!(
+                f,
+                " [x{:04X}]=x{:04X}->x{:04X}",
+                write.address, write.old, write.new
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes one record per executed instruction
+#[derive(Debug)]
+pub struct Tracer<W: Write> {
+    writer: W,
+    format: TraceFormat,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(mut writer: W, format: TraceFormat) -> io::Result<Self> {
+        if format == TraceFormat::Binary {
+            writer.write_all(BINARY_MAGIC)?;
+            writer.write_u8(BINARY_VERSION)?;
+        }
+        Ok(Tracer { writer, format })
+    }
+
+    pub fn record(&mut self, record: &TraceRecord) -> io::Result<()> {
+        match self.format {
+            TraceFormat::Jsonl => self.write_json(record),
+            TraceFormat::Binary => self.write_binary(record),
+        }
+    }
+
+    /// Flush the trace and give back the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_json(&mut self, record: &TraceRecord) -> io::Result<()> {
+        let registers: Vec<String> = record
+            .registers
+            .iter()
+            .map(|write| {
+                format!(
+                    "{{\"reg\":\"{}\",\"old\":{},\"new\":{}}}",
+                    register_name(write.register),
+                    write.old,
+                    write.new
+                )
+            })
+            .collect();
+        let memory: Vec<String> = record
+            .memory
+            .iter()
+            .map(|write| {
+                format!(
+                    "{{\"addr\":{},\"old\":{},\"new\":{}}}",
+                    write.address, write.old, write.new
+                )
+            })
+            .collect();
+        writeln!(
+            self.writer,
+            "{{\"cycle\":{},\"pc\":{},\"word\":{},\"asm\":\"{}\",\"cond\":\"{}\",\"regs\":[{}],\"mem\":[{}]}}",
+            record.cycle,
+            record.pc,
+            record.word,
+            escape(&record.disassembly()),
+            cond_name(record.cond),
+            registers.join(","),
+            memory.join(",")
+        )
+    }
+
+    fn write_binary(&mut self, record: &TraceRecord) -> io::Result<()> {
+        let w = &mut self.writer;
+        w.write_u64::<BigEndian>(record.cycle)?;
+        w.write_u16::<BigEndian>(record.pc)?;
+        w.write_u16::<BigEndian>(record.word)?;
+        w.write_u16::<BigEndian>(record.cond)?;
+        w.write_u8(record.registers.len() as u8)?;
+        for write in &record.registers {
+            w.write_u8(write.register as u8)?;
+            w.write_u16::<BigEndian>(write.old)?;
+            w.write_u16::<BigEndian>(write.new)?;
+        }
+        w.write_u16::<BigEndian>(record.memory.len() as u16)?;
+        for write in &record.memory {
+            w.write_u16::<BigEndian>(write.address)?;
+            w.write_u16::<BigEndian>(write.old)?;
+            w.write_u16::<BigEndian>(write.new)?;
+        }
+        Ok(())
+    }
+}
+
+/// Read a whole binary trace. JSON Lines traces are meant for people and other tools, only
+/// the binary format can be read back.
+pub fn read_trace<R: BufRead>(mut reader: R) -> io::Result<Vec<TraceRecord>> {
+    let mut magic = [0; 4];
+    match reader.read_exact(&mut magic) {
+        Err(e) if e.kind() != io::ErrorKind::UnexpectedEof => return Err(e),
+        _ if &magic != BINARY_MAGIC => {
+            return Err(invalid(
+                "not a binary trace, record it with --trace-format binary".to_string(),
+            ))
+        }
+        _ => {}
+    }
+    let version = reader.read_u8()?;
+    if version != BINARY_VERSION {
+        return Err(invalid(format!("unsupported trace version {}", version)));
+    }
+    let mut records = Vec::new();
+    loop {
+        let cycle = match reader.read_u64::<BigEndian>() {
+            Ok(cycle) => cycle,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        };
+        let pc = reader.read_u16::<BigEndian>()?;
+        let word = reader.read_u16::<BigEndian>()?;
+        let cond = reader.read_u16::<BigEndian>()?;
+        let mut registers = Vec::new();
+        for _ in 0..reader.read_u8()? {
+            let register = reader.read_u8()? as usize;
+            if register >= LC3CPURegister::ALL.len() {
+                return Err(invalid(format!("invalid register {}", register)));
+            }
+            let old = reader.read_u16::<BigEndian>()?;
+            let new = reader.read_u16::<BigEndian>()?;
+            registers.push(RegisterWrite { register, old, new });
+        }
+        let mut memory = Vec::new();
+        for _ in 0..reader.read_u16::<BigEndian>()? {
+            let address = reader.read_u16::<BigEndian>()?;
+            let old = reader.read_u16::<BigEndian>()?;
+            let new = reader.read_u16::<BigEndian>()?;
+            memory.push(MemoryWrite { address, old, new });
+        }
+        records.push(TraceRecord {
+            cycle,
+            pc,
+            word,
+            cond,
+            registers,
+            memory,
+        });
+    }
+    Ok(records)
+}
+
+/// Index of the first record where two traces disagree, or the length of the shorter trace
+/// when one is a prefix of the other. `None` when the traces are identical.
+pub fn first_difference(a: &[TraceRecord], b: &[TraceRecord]) -> Option<usize> {
+    match a.iter().zip(b).position(|(a, b)| a != b) {
+        Some(index) => Some(index),
+        None if a.len() != b.len() => Some(a.len().min(b.len())),
+        None => None,
+    }
+}
+
+/// Condition codes as written in JSON traces: `n`, `z` or `p`, or the raw COND value in hex
+/// when it holds no single flag
+fn cond_name(cond: u16) -> String {
+    match flags(cond) {
+        "?" => format!("x{:04X}", cond),
+        name => name.to_string(),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn register_name(register: usize) -> String {
+    format!("{:?}", LC3CPURegister::ALL[register])
+}
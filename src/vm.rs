@@ -0,0 +1,261 @@
+use crate::constant;
+use crate::cpu::{LC3Cpu, StepOutcome};
+use crate::device::{Device, Interrupt};
+use crate::error::VmError;
+use crate::input::InputEvent;
+use crate::io::IoBackend;
+use crate::journal::Journal;
+use crate::register::LC3CPURegister::{self, PC};
+use crate::snapshot::Snapshot;
+use crate::watch::{WatchHit, Watchpoint};
+use byteorder::{BigEndian, ReadBytesExt};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read};
+use std::path::Path;
+
+/// Read an object image without loading it, returns the origin address and the words that
+/// follow it
+pub fn read_image<R: Read>(mut reader: R) -> Result<(u16, Vec<u16>), VmError> {
+    // Note how we're using `read_u16` _and_ BigEndian to read the roms file.
+    // Most modern computers are little-endian (LE) but LC3 programs are big-endian (BE)
+    // First 16 bits tell the LC3 which address to load the program
+    let origin = reader.read_u16::<BigEndian>()?;
+    let mut words = Vec::new();
+    loop {
+        match reader.read_u16::<BigEndian>() {
+            Ok(word) => words.push(word),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    if origin as usize + words.len() > constant::MEMORY_MAX {
+        return Err(VmError::ImageTooLarge { origin });
+    }
+    Ok((origin, words))
+}
+
+/// Why [`Vm::run`] returned
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// The program executed `HALT`
+    Halted,
+    /// The last instruction triggered watchpoints
+    Watchpoint(Vec<WatchHit>),
+    /// The number of instructions given to [`Vm::run_until`] was executed
+    CycleReached,
+}
+
+/// A Little Computer 3 virtual machine that can be embedded in other programs.
+/// `Vm` owns the CPU state and exposes the operations a host needs: loading an image,
+/// stepping or running the program and inspecting registers and memory.
+#[derive(Debug, Default)]
+pub struct Vm {
+    cpu: LC3Cpu,
+    /// Undo journal, only recorded once enabled
+    journal: Option<Journal>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read image from a provided input path and point PC at its origin
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<(), VmError> {
+        let f = File::open(path)?;
+        self.load_from(BufReader::new(f))
+    }
+
+    /// Read image from any byte source. The first word is the origin address, the rest are
+    /// copied into memory starting from that address. PC is set to the origin so execution
+    /// starts at the first word of the image.
+    pub fn load_from<R: Read>(&mut self, reader: R) -> Result<(), VmError> {
+        let (origin, words) = read_image(reader)?;
+        // Straight into memory, an image covering the I/O page must not drive the devices
+        let start = origin as usize;
+        self.cpu.memory[start..start + words.len()].copy_from_slice(&words);
+        self.cpu.registers[PC as usize] = origin;
+        Ok(())
+    }
+
+    /// Connect the keyboard, the display and the trap routines to `io` instead of the
+    /// process console
+    pub fn set_io<B: IoBackend + 'static>(&mut self, io: B) {
+        self.cpu.bus.console.io = Box::new(io);
+    }
+
+    /// Log every input byte the program consumes together with the cycle it first saw it at
+    pub fn record_input(&mut self) {
+        self.cpu.bus.console.recorded = Some(Vec::new());
+    }
+
+    /// Input recorded since [`Vm::record_input`] was called
+    pub fn recorded_input(&self) -> Option<&[InputEvent]> {
+        self.cpu.bus.console.recorded.as_deref()
+    }
+
+    /// Feed the program exactly the bytes of a recorded log instead of reading the console.
+    /// Execution fails with [`VmError::ReplayDiverged`] when the program asks for input at a
+    /// different cycle than recorded.
+    pub fn replay_input(&mut self, events: Vec<InputEvent>) {
+        self.cpu.bus.console.replay = Some(events.into());
+    }
+
+    /// Plug a peripheral into the I/O page, after the keyboard, display and machine control
+    /// registers. Fails when its addresses lie outside 0xFE00 - 0xFFFF or overlap a device
+    /// already attached. The undo journal is cleared, its entries predate the device.
+    pub fn attach_device<D: Device + 'static>(&mut self, device: D) -> Result<(), VmError> {
+        self.cpu.bus.attach(Box::new(device))?;
+        if let Some(journal) = &mut self.journal {
+            *journal = Journal::new(journal.capacity());
+        }
+        Ok(())
+    }
+
+    /// Highest priority interrupt currently requested by a device
+    pub fn pending_interrupt(&self) -> Option<Interrupt> {
+        self.cpu.bus.interrupt()
+    }
+
+    /// Capture the complete machine state
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            registers: self.cpu.registers,
+            memory: self.cpu.memory.to_vec(),
+            halted: self.cpu.halted,
+            cycles: self.cpu.cycles,
+            devices: self.cpu.bus.save_states(),
+        }
+    }
+
+    /// Put the machine back into the state captured by `snapshot`. The undo journal is
+    /// cleared since it describes a different history, watchpoints are kept. A snapshot that
+    /// does not fit the machine is rejected and leaves it unchanged.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), VmError> {
+        if snapshot.memory.len() != constant::MEMORY_MAX {
+            return Err(VmError::InvalidSnapshot(format!(
+                "{} memory words instead of {}",
+                snapshot.memory.len(),
+                constant::MEMORY_MAX
+            )));
+        }
+        self.cpu.bus.load_states(&snapshot.devices)?;
+        self.cpu.registers = snapshot.registers;
+        self.cpu.memory.copy_from_slice(&snapshot.memory);
+        self.cpu.halted = snapshot.halted;
+        self.cpu.cycles = snapshot.cycles;
+        if let Some(journal) = &mut self.journal {
+            *journal = Journal::new(journal.capacity());
+        }
+        Ok(())
+    }
+
+    /// Write a snapshot of the machine to a file
+    pub fn save_state<P: AsRef<Path>>(&self, path: P) -> Result<(), VmError> {
+        let f = File::create(path)?;
+        Ok(self.snapshot().write(BufWriter::new(f))?)
+    }
+
+    /// Restore the machine from a snapshot file
+    pub fn load_state<P: AsRef<Path>>(&mut self, path: P) -> Result<(), VmError> {
+        let f = File::open(path)?;
+        let snapshot = Snapshot::read(BufReader::new(f))?;
+        self.restore(&snapshot)
+    }
+
+    /// Fetch, decode and execute a single instruction and report what it changed. On a fault PC
+    /// is left at the faulting instruction.
+    pub fn step(&mut self) -> Result<StepOutcome, VmError> {
+        // Devices change state on reads and ticks too, keep their state to undo the step
+        let devices = self.journal.is_some().then(|| self.cpu.bus.save_states());
+        let outcome = self.cpu.step()?;
+        if let (Some(journal), Some(devices)) = (&mut self.journal, devices) {
+            journal.record(&outcome, devices);
+        }
+        Ok(outcome)
+    }
+
+    /// Record the register and memory changes of the last `capacity` instructions so they can
+    /// be undone with [`Vm::step_back`]
+    pub fn enable_journal(&mut self, capacity: usize) {
+        self.journal = Some(Journal::new(capacity));
+    }
+
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    /// Undo the most recent instruction, returns its outcome or `None` when the journal is
+    /// disabled or exhausted. Device registers are rolled back, but console input and output
+    /// cannot be taken back: a key read again is not the same key press.
+    pub fn step_back(&mut self) -> Option<StepOutcome> {
+        self.journal.as_mut()?.undo(&mut self.cpu)
+    }
+
+    /// Run the fetch/decode/execute loop until the program halts, triggers a watchpoint or faults
+    pub fn run(&mut self) -> Result<StopReason, VmError> {
+        self.run_until(u64::MAX)
+    }
+
+    /// Run like [`Vm::run`], but also stop once `cycle` instructions have been executed. The
+    /// program has not halted then, a snapshot taken there can be resumed.
+    pub fn run_until(&mut self, cycle: u64) -> Result<StopReason, VmError> {
+        while self.cpu.cycles < cycle {
+            let outcome = self.step()?;
+            if outcome.halted {
+                return Ok(StopReason::Halted);
+            }
+            if !outcome.watch_hits.is_empty() {
+                return Ok(StopReason::Watchpoint(outcome.watch_hits));
+            }
+        }
+        Ok(StopReason::CycleReached)
+    }
+
+    /// Stop execution when memory matching `watchpoint` is accessed, returns its index
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        self.cpu.watchpoints.push(watchpoint);
+        self.cpu.watchpoints.len() - 1
+    }
+
+    pub fn remove_watchpoint(&mut self, index: usize) -> Option<Watchpoint> {
+        (index < self.cpu.watchpoints.len()).then(|| self.cpu.watchpoints.remove(index))
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.cpu.watchpoints.clear();
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.cpu.watchpoints
+    }
+
+    /// Whether the program has executed `HALT`
+    pub fn is_halted(&self) -> bool {
+        self.cpu.halted
+    }
+
+    /// Number of instructions executed so far
+    pub fn cycles(&self) -> u64 {
+        self.cpu.cycles
+    }
+
+    pub fn register(&self, register: LC3CPURegister) -> u16 {
+        self.cpu.registers[register as usize]
+    }
+
+    /// Write a register. COND and the condition codes in PSR always agree, writing either one
+    /// updates the other.
+    pub fn set_register(&mut self, register: LC3CPURegister, value: u16) {
+        self.cpu.set_register(register as usize, value);
+    }
+
+    /// Peek at a memory location without triggering memory mapped device side effects
+    pub fn memory(&self, address: u16) -> u16 {
+        self.cpu.peek(address)
+    }
+
+    pub fn set_memory(&mut self, address: u16, value: u16) {
+        self.cpu.mem_write(address, value);
+    }
+}
@@ -0,0 +1,65 @@
+/// Kind of memory access a watchpoint observes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// Both reads and writes
+    Access,
+}
+
+/// Stop execution when an instruction accesses memory in `start..=end`.
+/// With a `value` condition only accesses reading or writing that value trigger it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub kind: WatchKind,
+    pub value: Option<u16>,
+}
+
+impl Watchpoint {
+    /// Watch a single address
+    pub fn new(address: u16, kind: WatchKind) -> Self {
+        Watchpoint {
+            start: address,
+            end: address,
+            kind,
+            value: None,
+        }
+    }
+
+    /// Watch every address in `start..=end`
+    pub fn range(start: u16, end: u16, kind: WatchKind) -> Self {
+        Watchpoint {
+            start,
+            end,
+            kind,
+            value: None,
+        }
+    }
+
+    /// Only trigger when `value` is read or written
+    pub fn with_value(mut self, value: u16) -> Self {
+        self.value = Some(value);
+        self
+    }
+
+    /// Whether an access of `kind` (`Read` or `Write`) to `address` moving `value` triggers
+    pub(crate) fn matches(&self, kind: WatchKind, address: u16, value: u16) -> bool {
+        (self.start..=self.end).contains(&address)
+            && (self.kind == WatchKind::Access || self.kind == kind)
+            && self.value.is_none_or(|expected| expected == value)
+    }
+}
+
+/// A triggered watchpoint. For reads `old` and `new` are both the value read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchHit {
+    /// Address of the instruction that accessed memory
+    pub pc: u16,
+    pub address: u16,
+    /// `Read` or `Write`
+    pub kind: WatchKind,
+    pub old: u16,
+    pub new: u16,
+}
@@ -0,0 +1,196 @@
+use lc3_vm::asm::{assemble, AsmError};
+
+fn assemble_err(source: &str) -> AsmError {
+    assemble(source).unwrap_err()
+}
+
+#[test]
+fn directives_place_data_after_the_origin() {
+    let assembly = assemble(
+        "
+        .ORIG x3000
+        .FILL #-1
+        .FILL x1234
+        .BLKW 2
+        .STRINGZ \"hi\\n\"
+        .END
+        .FILL x9999 ; ignored after .END
+",
+    )
+    .unwrap();
+    assert_eq!(assembly.origin, 0x3000);
+    assert_eq!(
+        assembly.words,
+        vec![
+            0xFFFF,
+            0x1234,
+            0,
+            0,
+            b'h' as u16,
+            b'i' as u16,
+            b'\n' as u16,
+            0
+        ]
+    );
+    // Directives are data, not code
+    assert!(assembly.code.is_empty());
+    assert_eq!(assembly.line_map[&0x3000], 3);
+    assert_eq!(assembly.line_map[&0x3007], 6);
+}
+
+#[test]
+fn labels_resolve_forward_and_backward() {
+    let assembly = assemble(
+        "
+        .ORIG x3000
+LOOP    LD R0, DATA     ; forward reference
+        ADD R0, R0, #-1
+        BRp LOOP
+        JSR SUB
+        HALT
+SUB     RET
+DATA    .FILL #3
+        .END
+",
+    )
+    .unwrap();
+    assert_eq!(assembly.symbols["LOOP"], 0x3000);
+    assert_eq!(assembly.symbols["SUB"], 0x3005);
+    assert_eq!(assembly.symbols["DATA"], 0x3006);
+    assert_eq!(
+        assembly.words,
+        vec![0x2005, 0x103F, 0x03FD, 0x4801, 0xF025, 0xC1C0, 0x0003]
+    );
+    assert_eq!(assembly.code.len(), 6);
+}
+
+#[test]
+fn trap_aliases_assemble_to_their_vectors() {
+    let assembly = assemble(
+        "
+        .ORIG x3000
+        GETC
+        OUT
+        PUTS
+        IN
+        PUTSP
+        HALT
+        TRAP x25
+        .END
+",
+    )
+    .unwrap();
+    assert_eq!(
+        assembly.words,
+        vec![0xF020, 0xF021, 0xF022, 0xF023, 0xF024, 0xF025, 0xF025]
+    );
+}
+
+#[test]
+fn labels_out_of_range_are_rejected() {
+    let error = assemble_err(
+        "
+        .ORIG x3000
+        BRnzp FAR
+        .BLKW 300
+FAR     HALT
+        .END
+",
+    );
+    assert_eq!((error.line, error.column), (3, 15));
+    assert_eq!(error.message, "label FAR is too far away (300 words)");
+
+    let error = assemble_err(
+        "
+        .ORIG x3000
+        ADD R0, R0, #16
+        .END
+",
+    );
+    assert_eq!((error.line, error.column), (3, 21));
+    assert_eq!(error.message, "#16 is out of range [-16, 15]");
+}
+
+#[test]
+fn duplicate_and_undefined_labels_are_rejected() {
+    let error = assemble_err(
+        "
+        .ORIG x3000
+HERE    HALT
+HERE    HALT
+        .END
+",
+    );
+    assert_eq!((error.line, error.column), (4, 1));
+    assert_eq!(error.message, "duplicate label HERE");
+
+    let error = assemble_err(
+        "
+        .ORIG x3000
+        LEA R0, NOWHERE
+        .END
+",
+    );
+    assert_eq!((error.line, error.column), (3, 17));
+    assert_eq!(error.message, "undefined label NOWHERE");
+}
+
+#[test]
+fn labels_that_read_as_numbers_are_rejected() {
+    for name in ["x10", "b1", "XFF", "0x3000", "12"] {
+        let error = assemble_err(&format!(
+            "
+        .ORIG x3000
+        BR {name}
+{name}   HALT
+        .END
+"
+        ));
+        assert_eq!((error.line, error.column), (4, 1), "{}", name);
+        assert_eq!(error.message, format!("label {} reads as a number", name));
+    }
+    // Names that merely start like a literal are fine
+    let assembly = assemble(
+        "
+        .ORIG x3000
+        BR xylo
+xylo    .FILL bad
+bad     HALT
+        .END
+",
+    )
+    .unwrap();
+    assert_eq!(assembly.words, vec![0x0E00, 0x3002, 0xF025]);
+}
+
+#[test]
+fn errors_report_line_and_column() {
+    let error = assemble_err("        ADD R0, R0, #1\n");
+    assert_eq!(error.to_string(), "1:9: statement before .ORIG");
+
+    let error = assemble_err(
+        "
+        .ORIG x3000
+        ADD R0, R9, #1
+        .END
+",
+    );
+    assert_eq!(error.to_string(), "3:17: expected a register, found R9");
+
+    assert_eq!(assemble_err("").message, "missing .ORIG");
+}
+
+#[test]
+fn columns_count_characters_not_bytes() {
+    let error = assemble_err(
+        "
+        .ORIG x3000
+        .STRINGZ \"é\" EXTRA
+        .END
+",
+    );
+    assert_eq!((error.line, error.column), (3, 22));
+
+    let error = assemble_err(".ORIG x3000\n.STRINGZ \"naïve\"\n.END\n");
+    assert_eq!(error.message, "only ASCII characters are supported");
+}
@@ -0,0 +1,15 @@
+use lc3_vm::asm::assemble;
+use lc3_vm::io::BufferIo;
+use lc3_vm::Vm;
+
+/// Assemble `source` and load it into a machine whose console has no input and collects the
+/// output in memory
+pub fn vm(source: &str) -> Vm {
+    let assembly = assemble(source).unwrap();
+    let mut image = Vec::new();
+    assembly.write_obj(&mut image).unwrap();
+    let mut vm = Vm::new();
+    vm.load_from(image.as_slice()).unwrap();
+    vm.set_io(BufferIo::new(Vec::new()));
+    vm
+}
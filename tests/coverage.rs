@@ -0,0 +1,112 @@
+mod common;
+
+use lc3_vm::asm::{assemble, Assembly};
+use lc3_vm::coverage::{BranchCount, Coverage};
+use std::env;
+use std::fs::{self, File};
+use std::process::{Command, Stdio};
+
+const PROGRAM: &str = "        .ORIG x3000
+        AND R1, R1, #0
+        ADD R1, R1, #2
+LOOP    ADD R1, R1, #-1
+        BRn NEVER
+        BRp LOOP
+        HALT
+NEVER   ADD R2, R2, #1
+        HALT
+        .END
+";
+
+fn coverage() -> (Coverage, Assembly) {
+    let assembly = assemble(PROGRAM).unwrap();
+    let mut vm = common::vm(PROGRAM);
+    let mut coverage = Coverage::new();
+    loop {
+        let outcome = vm.step().unwrap();
+        coverage.record(&outcome);
+        if outcome.halted {
+            return (coverage, assembly);
+        }
+    }
+}
+
+#[test]
+fn branches_count_taken_and_not_taken() {
+    let (coverage, _) = coverage();
+    assert_eq!(coverage.count(0x3002), 2);
+    assert_eq!(
+        coverage.branch(0x3003),
+        Some(BranchCount {
+            taken: 0,
+            not_taken: 2
+        })
+    );
+    assert_eq!(
+        coverage.branch(0x3004),
+        Some(BranchCount {
+            taken: 1,
+            not_taken: 1
+        })
+    );
+    assert_eq!(coverage.count(0x3006), 0);
+}
+
+#[test]
+fn annotated_listing_marks_unexecuted_lines() {
+    let (coverage, assembly) = coverage();
+    let listing = coverage.annotate(PROGRAM, &assembly);
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(lines[0], "        -:    1:        .ORIG x3000");
+    assert_eq!(lines[3], "        2:    4:LOOP    ADD R1, R1, #-1");
+    assert_eq!(
+        lines[5],
+        "                branch x3003 taken 0, not taken 2"
+    );
+    assert_eq!(lines[9], "    #####:    8:NEVER   ADD R2, R2, #1");
+}
+
+#[test]
+fn lcov_export() {
+    let (coverage, assembly) = coverage();
+    let lcov = coverage.lcov("loop.asm", &assembly);
+    assert!(lcov.starts_with("TN:\nSF:loop.asm\n"));
+    assert!(lcov.contains("BRDA:5,0,0,0\nBRDA:5,0,1,2\n"));
+    assert!(lcov.contains("BRF:4\nBRH:3\n"));
+    assert!(lcov.contains("DA:8,0\n"));
+    assert!(lcov.ends_with("LF:8\nLH:6\nend_of_record\n"));
+}
+
+#[test]
+fn stale_source_is_refused() {
+    let path = env::temp_dir().join(format!("lc3-vm-coverage-{}.obj", std::process::id()));
+    assemble(PROGRAM)
+        .unwrap()
+        .write_obj(File::create(&path).unwrap())
+        .unwrap();
+    let source = PROGRAM.replace("#2", "#3");
+    fs::write(path.with_extension("asm"), &source).unwrap();
+    let lcov = path.with_extension("lcov");
+    let run = || {
+        Command::new(env!("CARGO_BIN_EXE_lc3-vm"))
+            .arg("--no-raw")
+            .arg("--coverage")
+            .arg(&lcov)
+            .arg(&path)
+            .stdin(Stdio::null())
+            .output()
+            .unwrap()
+    };
+    let output = run();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("does not assemble to"), "{}", stderr);
+    assert!(!lcov.exists());
+
+    fs::write(path.with_extension("asm"), PROGRAM).unwrap();
+    assert!(run().status.success());
+    assert!(lcov.exists());
+    for path in [&path, &path.with_extension("asm"), &lcov] {
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use common::vm;
+use lc3_vm::asm::assemble;
+use lc3_vm::debugger::Debugger;
+use lc3_vm::io::BufferIo;
+use std::fs::File;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
+use std::{env, thread};
+
+const GETC: &str = "
+        .ORIG x3000
+        GETC
+        OUT
+        HALT
+        .END
+";
+
+#[test]
+fn repl_steps_over_a_getc() {
+    let mut vm = vm(GETC);
+    vm.set_io(BufferIo::new(b"x".to_vec()));
+    let mut debugger = Debugger::new(vm);
+    let mut output = Vec::new();
+    debugger
+        .repl(b"step\nregs\nquit\n".as_slice(), &mut output)
+        .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("R0   x0078  #120\n"), "{}", output);
+    assert!(output.contains("PC   x3001"), "{}", output);
+}
+
+/// Run the debugger binary on `source`, writing each chunk of `commands` to its stdin after
+/// a pause so the program has run by then
+fn debug_over_pipe(name: &str, source: &str, commands: &[&[u8]]) -> Output {
+    let path = env::temp_dir().join(format!("lc3-vm-debug-{}-{}.obj", name, std::process::id()));
+    assemble(source)
+        .unwrap()
+        .write_obj(File::create(&path).unwrap())
+        .unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lc3-vm"))
+        .arg("--debug")
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    for chunk in commands {
+        stdin.write_all(chunk).unwrap();
+        thread::sleep(Duration::from_millis(200));
+    }
+    drop(stdin);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while child.try_wait().unwrap().is_none() {
+        if Instant::now() > deadline {
+            child.kill().unwrap();
+            panic!("the debugger hangs");
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    std::fs::remove_file(&path).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn program_and_prompt_share_stdin() {
+    // `x` is the key GETC reads, between the `step` and `regs` commands
+    let output = debug_over_pipe("getc", GETC, &[b"step\nx\nregs\nquit\n"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("R0   x0078  #120\n"), "{}", stdout);
+}
+
+#[test]
+fn keyboard_polls_leave_the_commands_intact() {
+    let source = "
+        .ORIG x3000
+POLL    LDI R0, KBSR
+        BRnzp POLL
+KBSR    .FILL xFE00
+        .END
+";
+    // The poll is waiting for a key when the next commands arrive, they belong to the prompt
+    let output = debug_over_pipe("poll", source, &[b"step\n", b"step\nregs\n", b"quit\n"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.contains("unknown command"), "{}", stdout);
+    assert!(stdout.contains("PC   x3000"), "{}", stdout);
+}
+
+#[test]
+fn listings_are_bounded_by_the_address_space() {
+    let mut debugger = Debugger::new(vm(GETC));
+    let mut output = Vec::new();
+    debugger
+        .repl(
+            b"mem 0 18446744073709551615\n\
+              disasm 0 18446744073709551615\n\
+              watch 0 18446744073709551615\n\
+              quit\n"
+                .as_slice(),
+            &mut output,
+        )
+        .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    // One row per 8 words, then one line per word
+    let lines: Vec<&str> = output
+        .lines()
+        .map(|line| line.trim_start_matches("(lc3) "))
+        .filter(|line| line.contains(": x"))
+        .collect();
+    let rows = lines.iter().filter(|line| !line.contains("  ")).count();
+    assert_eq!(rows, 0x10000 / 8);
+    let instructions = lines.iter().filter(|line| !line.starts_with('x')).count();
+    assert_eq!(instructions, 0x10000);
+    assert!(output.contains("invalid length 18446744073709551615"));
+}
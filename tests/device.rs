@@ -0,0 +1,183 @@
+mod common;
+
+use common::vm;
+use lc3_vm::device::{Device, DeviceContext, Interrupt};
+use lc3_vm::register::LC3CPURegister::{PC, R0, R1};
+use lc3_vm::VmError;
+use std::io;
+use std::ops::RangeInclusive;
+
+/// Counts executed instructions, reading the counter clears it. Requests an interrupt once it
+/// reaches the limit written to the second register.
+#[derive(Default)]
+struct Timer {
+    count: u16,
+    limit: u16,
+}
+
+const COUNT: u16 = 0xFE10;
+const LIMIT: u16 = 0xFE11;
+
+impl Device for Timer {
+    fn range(&self) -> RangeInclusive<u16> {
+        COUNT..=LIMIT
+    }
+
+    fn read(&mut self, address: u16, _: &mut DeviceContext) -> Result<u16, VmError> {
+        let value = self.peek(address);
+        if address == COUNT {
+            self.count = 0;
+        }
+        Ok(value)
+    }
+
+    fn write(&mut self, address: u16, value: u16, _: &mut DeviceContext) -> Result<(), VmError> {
+        if address == LIMIT {
+            self.limit = value;
+        }
+        Ok(())
+    }
+
+    fn peek(&self, address: u16) -> u16 {
+        if address == COUNT {
+            self.count
+        } else {
+            self.limit
+        }
+    }
+
+    fn tick(&mut self, _: &mut DeviceContext) -> Result<(), VmError> {
+        self.count += 1;
+        Ok(())
+    }
+
+    fn interrupt(&self) -> Option<Interrupt> {
+        (self.limit != 0 && self.count >= self.limit).then_some(Interrupt {
+            vector: 0x81,
+            priority: 2,
+        })
+    }
+}
+
+#[test]
+fn accesses_are_dispatched_to_the_attached_device() {
+    let mut vm = vm("
+        .ORIG x3000
+        ADD R1, R1, #1
+        ADD R1, R1, #1
+        LDI R0, COUNT
+        LDI R1, COUNT
+        HALT
+COUNT   .FILL xFE10
+        .END
+");
+    vm.attach_device(Timer::default()).unwrap();
+    vm.run().unwrap();
+    // Two instructions completed before the first read, which cleared the counter
+    assert_eq!(vm.register(R0), 2);
+    assert_eq!(vm.register(R1), 1);
+    // Memory behind the device is untouched
+    assert_eq!(vm.snapshot().memory[COUNT as usize], 0);
+}
+
+#[test]
+fn devices_raise_their_interrupt_line() {
+    let mut vm = vm("
+        .ORIG x3000
+        AND R0, R0, #0
+        ADD R0, R0, #3
+        STI R0, LIMIT
+        HALT
+LIMIT   .FILL xFE11
+        .END
+");
+    vm.attach_device(Timer::default()).unwrap();
+    for _ in 0..3 {
+        vm.step().unwrap();
+    }
+    assert_eq!(vm.memory(LIMIT), 3);
+    assert_eq!(
+        vm.pending_interrupt(),
+        Some(Interrupt {
+            vector: 0x81,
+            priority: 2
+        })
+    );
+}
+
+#[test]
+fn rejects_devices_outside_the_io_page_or_overlapping() {
+    struct At(RangeInclusive<u16>);
+
+    impl Device for At {
+        fn range(&self) -> RangeInclusive<u16> {
+            self.0.clone()
+        }
+
+        fn read(&mut self, _: u16, _: &mut DeviceContext) -> Result<u16, VmError> {
+            Ok(0)
+        }
+
+        fn write(&mut self, _: u16, _: u16, _: &mut DeviceContext) -> Result<(), VmError> {
+            Ok(())
+        }
+
+        fn peek(&self, _: u16) -> u16 {
+            0
+        }
+    }
+
+    let mut vm = vm("
+        .ORIG x3000
+        HALT
+        .END
+");
+    assert!(matches!(
+        vm.attach_device(At(0x4000..=0x4001)),
+        Err(VmError::DeviceConflict(0x4000))
+    ));
+    // KBDR belongs to the keyboard
+    assert!(matches!(
+        vm.attach_device(At(0xFE02..=0xFE03)),
+        Err(VmError::DeviceConflict(0xFE02))
+    ));
+    vm.attach_device(At(0xFE08..=0xFE09)).unwrap();
+}
+
+/// Fails every read, the word behind it decodes as the reserved opcode
+struct Faulty;
+
+impl Device for Faulty {
+    fn range(&self) -> RangeInclusive<u16> {
+        0xFE20..=0xFE20
+    }
+
+    fn read(&mut self, _: u16, _: &mut DeviceContext) -> Result<u16, VmError> {
+        Err(VmError::Io(io::Error::other("bus fault")))
+    }
+
+    fn write(&mut self, _: u16, _: u16, _: &mut DeviceContext) -> Result<(), VmError> {
+        Ok(())
+    }
+
+    fn peek(&self, _: u16) -> u16 {
+        0xD000
+    }
+}
+
+#[test]
+fn device_errors_are_reported_once() {
+    let mut vm = vm("
+        .ORIG x3000
+        ADD R1, R1, #1
+        HALT
+        .END
+");
+    vm.attach_device(Faulty).unwrap();
+    // Fetching from the device fails before the word it yields faults
+    vm.set_register(PC, 0xFE20);
+    assert!(matches!(vm.step(), Err(VmError::Io(_))));
+    vm.set_register(PC, 0x3000);
+    vm.step().unwrap();
+    assert_eq!(vm.register(R1), 1);
+}
@@ -0,0 +1,79 @@
+use lc3_vm::asm::assemble;
+use lc3_vm::disasm::{branch_labels, disassemble, disassemble_at, listing, Labels};
+
+fn labels(entries: &[(u16, &str)]) -> Labels {
+    entries
+        .iter()
+        .map(|&(address, name)| (address, name.to_string()))
+        .collect()
+}
+
+#[test]
+fn every_word_reassembles_to_itself() {
+    for word in 0..=u16::MAX {
+        let text = disassemble(word);
+        let assembly = assemble(&format!(".ORIG x3000\n{}\n.END\n", text))
+            .unwrap_or_else(|e| panic!("x{:04X} `{}`: {}", word, text, e));
+        assert_eq!(assembly.words, vec![word], "x{:04X} `{}`", word, text);
+    }
+}
+
+#[test]
+fn pc_relative_operands_are_resolved_against_the_incremented_pc() {
+    // BRnz with offset -6, located at x3005 so the target is x3000
+    let word = 0x0DFA;
+    assert_eq!(disassemble(word), "BRnz #-6");
+    assert_eq!(disassemble_at(word, 0x3005, &Labels::new()), "BRnz x3000");
+    assert_eq!(
+        disassemble_at(word, 0x3005, &labels(&[(0x3000, "LOOP")])),
+        "BRnz LOOP"
+    );
+    // Negative offsets wrap around the bottom of memory
+    assert_eq!(
+        disassemble_at(0x23FF, 0x0000, &Labels::new()),
+        "LD R1, x0000"
+    );
+    assert_eq!(
+        disassemble_at(0x2200, 0xFFFF, &Labels::new()),
+        "LD R1, x0000"
+    );
+    assert_eq!(
+        disassemble_at(0x4FFE, 0x3010, &labels(&[(0x300F, "SUB")])),
+        "JSR SUB"
+    );
+}
+
+#[test]
+fn words_that_are_not_instructions_are_shown_as_fill() {
+    // Reserved opcode and a branch that tests no condition code
+    assert_eq!(disassemble(0xD123), ".FILL xD123");
+    assert_eq!(disassemble(0x0005), ".FILL x0005");
+    // Instructions with bits set in fields that must be zero
+    assert_eq!(disassemble(0x1058), ".FILL x1058");
+    assert_eq!(disassemble(0xC1C1), ".FILL xC1C1");
+    assert_eq!(disassemble(0xC1C0), "RET");
+    assert_eq!(
+        disassemble_at(0xD000, 0x3000, &Labels::new()),
+        ".FILL xD000"
+    );
+}
+
+#[test]
+fn listing_labels_branch_targets_inside_the_image() {
+    let assembly = assemble(
+        "
+        .ORIG x3000
+LOOP    ADD R1, R1, #-1
+        BRp LOOP
+        HALT
+        .END
+",
+    )
+    .unwrap();
+    let labels = branch_labels(assembly.origin, &assembly.words);
+    assert_eq!(labels, self::labels(&[(0x3000, "L3000")]));
+    let listing = listing(assembly.origin, &assembly.words);
+    assert!(listing.contains("x3000  x127F  L3000   ADD R1, R1, #-1\n"));
+    assert!(listing.contains("x3001  x03FE          BRp L3000\n"));
+    assert!(listing.contains("x3002  xF025          TRAP x25\n"));
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use common::vm;
+use lc3_vm::io::BufferIo;
+use lc3_vm::register::LC3CPURegister::R2;
+use lc3_vm::Vm;
+
+/// Print a string one character at a time, waiting for the display before each one
+const POLLED_OUTPUT: &str = "
+        .ORIG x3000
+        LEA R1, TEXT
+NEXT    LDR R0, R1, #0
+        BRz DONE
+WAIT    LDI R2, DSR
+        BRzp WAIT
+        STI R0, DDR
+        ADD R1, R1, #1
+        BRnzp NEXT
+DONE    HALT
+DSR     .FILL xFE04
+DDR     .FILL xFE06
+TEXT    .STRINGZ \"polled\\n\"
+        .END
+";
+
+#[test]
+fn polled_output_loop_prints_through_the_backend() {
+    let mut vm = vm(POLLED_OUTPUT);
+    let io = BufferIo::new(Vec::new());
+    let output = io.output();
+    vm.set_io(io);
+    vm.run().unwrap();
+    assert_eq!(
+        output.lock().unwrap().as_slice(),
+        b"polled\nHALT detected\n"
+    );
+    assert_eq!(vm.register(R2), 0x8000);
+}
+
+#[test]
+fn display_is_ready_from_reset() {
+    assert_eq!(Vm::new().memory(0xFE04), 0x8000);
+}
+
+#[test]
+fn outcome_reports_the_ddr_write() {
+    let mut vm = vm(POLLED_OUTPUT);
+    vm.set_io(BufferIo::new(Vec::new()));
+    let outcome = loop {
+        let outcome = vm.step().unwrap();
+        if !outcome.memory_written.is_empty() {
+            break outcome;
+        }
+    };
+    assert_eq!(outcome.memory_written[0].address, 0xFE06);
+    assert_eq!(outcome.memory_written[0].new, b'p' as u16);
+}
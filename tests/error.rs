@@ -0,0 +1,66 @@
+mod common;
+
+use common::vm;
+use lc3_vm::register::LC3CPURegister::PC;
+use lc3_vm::{Vm, VmError};
+
+#[test]
+fn reserved_opcode_is_an_illegal_opcode() {
+    let mut vm = vm("
+        .ORIG x3000
+        .FILL xD123
+        .END
+");
+    assert!(matches!(vm.step(), Err(VmError::IllegalOpcode(0xD123))));
+    // PC still points at the faulting instruction
+    assert_eq!(vm.register(PC), 0x3000);
+    assert!(!vm.is_halted());
+}
+
+#[test]
+fn unknown_trap_vector_is_reported() {
+    let mut vm = vm("
+        .ORIG x3000
+        TRAP x26
+        .END
+");
+    assert!(matches!(vm.step(), Err(VmError::UnknownTrap(0x26))));
+    assert_eq!(vm.register(PC), 0x3000);
+}
+
+#[test]
+fn faults_in_trap_routines_leave_pc_at_the_trap() {
+    let mut vm = vm("
+        .ORIG x3000
+        ADD R1, R1, #1
+        GETC
+        .END
+");
+    vm.step().unwrap();
+    // The console has no input left, GETC cannot complete
+    assert!(matches!(vm.step(), Err(VmError::Io(_))));
+    assert_eq!(vm.register(PC), 0x3001);
+}
+
+#[test]
+fn image_past_the_end_of_memory_is_rejected() {
+    // Origin xFFFF followed by two words
+    let image = [0xFF, 0xFF, 0x12, 0x34, 0x56, 0x78];
+    let mut vm = Vm::new();
+    assert!(matches!(
+        vm.load_from(image.as_slice()),
+        Err(VmError::ImageTooLarge { origin: 0xFFFF })
+    ));
+}
+
+#[test]
+fn errors_describe_the_fault() {
+    assert_eq!(
+        VmError::IllegalOpcode(0xD123).to_string(),
+        "illegal opcode in instruction 0xd123"
+    );
+    assert_eq!(
+        VmError::UnknownTrap(0x26).to_string(),
+        "unknown trap vector 0x26"
+    );
+}
@@ -0,0 +1,157 @@
+//! Drives the GDB stub with a scripted client over a local TCP connection.
+mod common;
+
+use lc3_vm::gdbstub::GdbStub;
+use std::io::{BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+
+const PROGRAM: &str = "
+        .ORIG x3000
+        AND R0, R0, #0
+        ADD R0, R0, #2
+LOOP    ADD R0, R0, #-1
+        BRp LOOP
+        HALT
+        .END
+";
+
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Client {
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0; 1];
+        self.reader.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    /// Send a packet, wait for the acknowledgement and return the reply payload
+    fn request(&mut self, payload: impl AsRef<[u8]>) -> String {
+        let payload = payload.as_ref();
+        let checksum = payload
+            .iter()
+            .fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        self.writer.write_all(b"$").unwrap();
+        self.writer.write_all(payload).unwrap();
+        write!(self.writer, "#{:02x}", checksum).unwrap();
+        let text = String::from_utf8_lossy(payload);
+        assert_eq!(self.read_byte(), b'+', "ack for {}", text);
+
+        assert_eq!(self.read_byte(), b'$');
+        let mut reply = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => reply.push(byte),
+            }
+        }
+        let mut checksum = [0; 2];
+        self.reader.read_exact(&mut checksum).unwrap();
+        let expected = reply.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        assert_eq!(
+            u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap(),
+            expected
+        );
+        self.writer.write_all(b"+").unwrap();
+        String::from_utf8(reply).unwrap()
+    }
+}
+
+/// Serve `PROGRAM` on a local port, the server returns whether the program halted
+fn connect() -> (Client, JoinHandle<bool>) {
+    let vm = common::vm(PROGRAM);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let mut stub = GdbStub::new(vm);
+        stub.listen(&listener).unwrap();
+        stub.vm().is_halted()
+    });
+
+    let stream = TcpStream::connect(address).unwrap();
+    stream.set_nodelay(true).unwrap();
+    let client = Client {
+        reader: BufReader::new(stream.try_clone().unwrap()),
+        writer: stream,
+    };
+    (client, server)
+}
+
+#[test]
+fn scripted_session() {
+    let (mut client, server) = connect();
+    assert!(client.request("qSupported:swbreak+").contains("PacketSize"));
+    assert_eq!(client.request("?"), "S05");
+
+    // R0 - R7, PC and PSR, 16-bit little-endian each
+    let registers = client.request("g");
+    assert_eq!(registers.len(), 40);
+    assert_eq!(&registers[32..36], "0030");
+
+    // Word x3000 lives at byte x6000
+    assert_eq!(client.request("m6000,2"), "2050");
+
+    // Break on LOOP (x3002)
+    assert_eq!(client.request("Z0,6004,2"), "OK");
+    assert_eq!(client.request("c"), "S05");
+    assert_eq!(client.request("p8"), "0230");
+    assert_eq!(client.request("p0"), "0200");
+
+    assert_eq!(client.request("s"), "S05");
+    assert_eq!(client.request("p8"), "0330");
+
+    assert_eq!(client.request("c"), "S05");
+    assert_eq!(client.request("p0"), "0100");
+    assert_eq!(client.request("z0,6004,2"), "OK");
+
+    assert_eq!(client.request("M6010,2:3412"), "OK");
+    assert_eq!(client.request("m6010,2"), "3412");
+    assert_eq!(client.request("P1=ffff"), "OK");
+    assert_eq!(client.request("p1"), "ffff");
+
+    // Runs to HALT
+    assert_eq!(client.request("c"), "W00");
+    write!(client.writer, "$k#6b").unwrap();
+
+    assert!(server.join().unwrap());
+}
+
+#[test]
+fn malformed_packets_are_rejected() {
+    let (mut client, server) = connect();
+    // Non-ASCII bytes where hex digits are expected
+    let mut registers = "00".repeat(19).into_bytes();
+    registers.insert(0, b'G');
+    registers.extend("é".as_bytes());
+    assert_eq!(client.request(&registers), "E01");
+    assert_eq!(client.request("M6010,2:é"), "E01");
+    assert_eq!(client.request("P1=éé"), "E01");
+    assert_eq!(client.request("p+1"), "E01");
+    assert_eq!(client.request("Z0,6004"), "E01");
+    // The stub keeps serving after rejecting them
+    assert_eq!(client.request("p8"), "0030");
+    write!(client.writer, "$k#6b").unwrap();
+    assert!(!server.join().unwrap());
+}
+
+#[test]
+fn out_of_range_packets_are_rejected() {
+    let (mut client, server) = connect();
+    assert_eq!(client.request("m1ffff,2"), "E01");
+    assert_eq!(client.request("mffffffffffffffff,ffffffffffffffff"), "E01");
+    assert_eq!(client.request("m10000000000000000,2"), "E01");
+    assert_eq!(client.request("M1fffe,4:00000000"), "E01");
+    assert_eq!(client.request("Z2,6000,ffffffffffffffff"), "E01");
+    assert_eq!(client.request("Z2,ffffffffffffffff,2"), "E01");
+    assert_eq!(client.request("pa"), "E01");
+    assert_eq!(client.request("m1fffe,2"), "0000");
+    // Replies must fit in the advertised packet size
+    assert!(client.request("qSupported").contains("PacketSize=4000"));
+    assert_eq!(client.request("m0,2000").len(), 0x4000);
+    assert_eq!(client.request("m0,2001"), "E01");
+    write!(client.writer, "$k#6b").unwrap();
+    assert!(!server.join().unwrap());
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use common::vm;
+use lc3_vm::io::BufferIo;
+use lc3_vm::register::LC3CPURegister::{PC, R1};
+use lc3_vm::trap::TrapRoutine;
+use lc3_vm::{StopReason, VmError};
+
+const PROGRAM: &str = "
+        .ORIG x3000
+        ADD R1, R1, #7
+        HALT
+        ADD R1, R1, #1
+        .END
+";
+
+#[test]
+fn halt_step_reports_a_halted_outcome() {
+    let mut vm = vm(PROGRAM);
+    let io = BufferIo::new(Vec::new());
+    let output = io.output();
+    vm.set_io(io);
+    assert!(!vm.step().unwrap().halted);
+    let outcome = vm.step().unwrap();
+    assert!(outcome.halted);
+    assert_eq!(outcome.trap, Some(TrapRoutine::HALT));
+    assert_eq!(outcome.pc_after, 0x3002);
+    assert_eq!(output.lock().unwrap().as_slice(), b"HALT detected\n");
+    // The final state stays available
+    assert!(vm.is_halted());
+    assert_eq!(vm.cycles(), 2);
+    assert_eq!(vm.register(R1), 7);
+    assert_eq!(vm.register(PC), 0x3002);
+}
+
+#[test]
+fn halted_machine_does_not_step_any_further() {
+    let mut vm = vm(PROGRAM);
+    assert_eq!(vm.run().unwrap(), StopReason::Halted);
+    assert!(matches!(vm.step(), Err(VmError::Halted)));
+    assert_eq!(vm.cycles(), 2);
+    assert_eq!(vm.register(R1), 7);
+}
+
+#[test]
+fn programs_run_one_after_another_in_one_process() {
+    let mut first = vm(PROGRAM);
+    let mut second = vm(PROGRAM);
+    first.run().unwrap();
+    second.run().unwrap();
+    assert!(first.is_halted() && second.is_halted());
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use lc3_vm::input::{read_log, write_log, InputEvent};
+use lc3_vm::io::ScriptedIo;
+use lc3_vm::register::LC3CPURegister::R1;
+use lc3_vm::{Vm, VmError};
+
+/// Sum the first two keys, reading one through GETC and one by polling the keyboard
+const PROGRAM: &str = "
+        .ORIG x3000
+        GETC
+        ADD R1, R0, #0
+POLL    LDI R2, KBSR
+        BRzp POLL
+        LDI R2, KBDR
+        ADD R1, R1, R2
+        HALT
+KBSR    .FILL xFE00
+KBDR    .FILL xFE02
+        .END
+";
+
+fn vm() -> Vm {
+    common::vm(PROGRAM)
+}
+
+fn events() -> Vec<InputEvent> {
+    vec![
+        InputEvent { cycle: 0, byte: 3 },
+        InputEvent { cycle: 2, byte: 4 },
+    ]
+}
+
+#[test]
+fn replay_feeds_the_logged_bytes() {
+    let mut vm = vm();
+    vm.replay_input(events());
+    vm.record_input();
+    vm.run().unwrap();
+    assert_eq!(vm.register(R1), 7);
+    assert_eq!(vm.recorded_input(), Some(&events()[..]));
+}
+
+#[test]
+fn replay_detects_divergence() {
+    let mut vm = vm();
+    vm.replay_input(vec![
+        InputEvent { cycle: 0, byte: 3 },
+        InputEvent { cycle: 5, byte: 4 },
+    ]);
+    assert!(matches!(
+        vm.run(),
+        Err(VmError::ReplayDiverged {
+            cycle: 6,
+            expected: 5
+        })
+    ));
+}
+
+#[test]
+fn waiting_on_an_exhausted_log_is_an_error() {
+    let mut vm = vm();
+    vm.replay_input(Vec::new());
+    assert!(matches!(vm.run(), Err(VmError::Io(_))));
+}
+
+#[test]
+fn polling_replays_past_the_end_of_the_log() {
+    // Sum the keys seen over 20 polls of the status register
+    let source = "
+        .ORIG x3000
+        AND R1, R1, #0
+        LD R3, TRIES
+POLL    LDI R2, KBSR
+        BRzp NOKEY
+        LDI R2, KBDR
+        ADD R1, R1, R2
+NOKEY   ADD R3, R3, #-1
+        BRp POLL
+        HALT
+TRIES   .FILL #20
+KBSR    .FILL xFE00
+KBDR    .FILL xFE02
+        .END
+";
+    let mut recording = common::vm(source);
+    recording.set_io(ScriptedIo::new().after(3, [5]).after(4, [6]));
+    recording.record_input();
+    recording.run().unwrap();
+    assert_eq!(recording.register(R1), 11);
+    let log = recording.recorded_input().unwrap().to_vec();
+    assert_eq!(log.len(), 2);
+
+    // The polls after the last logged key find none instead of failing
+    let mut replay = common::vm(source);
+    replay.replay_input(log);
+    replay.run().unwrap();
+    assert_eq!(replay.register(R1), 11);
+    assert_eq!(replay.cycles(), recording.cycles());
+}
+
+#[test]
+fn log_round_trip() {
+    let mut file = Vec::new();
+    write_log(&events(), &mut file).unwrap();
+    assert_eq!(
+        String::from_utf8(file.clone()).unwrap(),
+        "# lc3-vm input log v1\n0 3\n2 4\n"
+    );
+    assert_eq!(read_log(file.as_slice()).unwrap(), events());
+    assert!(read_log(&b"12\n"[..]).is_err());
+}
@@ -0,0 +1,95 @@
+//! Exhaustive checks of the decoder and encoder over every 16-bit word.
+use lc3_vm::instruction::{Instruction, LC3Instruction, Operand};
+
+/// Whether the bits the ISA leaves unspecified hold their canonical value
+fn is_canonical(word: u16) -> bool {
+    match word >> 12 {
+        0b0001 | 0b0101 => word & 0x20 != 0 || word & 0x18 == 0,
+        0b1001 => word & 0x3F == 0x3F,
+        0b1100 => word & 0x0E3F == 0,
+        0b0100 => word & 0x0800 != 0 || word & 0x063F == 0,
+        0b1000 => word & 0x0FFF == 0,
+        0b1111 => word & 0x0F00 == 0,
+        _ => true,
+    }
+}
+
+#[test]
+fn encode_round_trips_every_canonical_word() {
+    for word in 0..=u16::MAX {
+        let decoded = Instruction::decode(word);
+        let encoded = decoded.encode();
+        assert_eq!(Instruction::decode(encoded), decoded, "word {:#06x}", word);
+        assert_eq!(encoded == word, is_canonical(word), "word {:#06x}", word);
+    }
+}
+
+#[test]
+fn opcode_matches_opcode_decoder() {
+    for word in 0..=u16::MAX {
+        assert_eq!(
+            Instruction::decode(word).opcode(),
+            LC3Instruction::from_bytes(word).unwrap(),
+            "word {:#06x}",
+            word
+        );
+    }
+}
+
+#[test]
+fn decodes_operands() {
+    assert_eq!(
+        Instruction::decode(0x147D),
+        Instruction::Add {
+            dr: 2,
+            sr1: 1,
+            operand: Operand::Imm(-3)
+        }
+    );
+    assert_eq!(
+        Instruction::decode(0x5042),
+        Instruction::And {
+            dr: 0,
+            sr1: 1,
+            operand: Operand::Reg(2)
+        }
+    );
+    assert_eq!(
+        Instruction::decode(0x0DFE),
+        Instruction::Br {
+            n: true,
+            z: true,
+            p: false,
+            offset: -2
+        }
+    );
+    assert_eq!(Instruction::decode(0x4FFF), Instruction::Jsr { offset: -1 });
+    assert_eq!(Instruction::decode(0x41C0), Instruction::Jsrr { base: 7 });
+    assert_eq!(Instruction::decode(0xC1C0), Instruction::Jmp { base: 7 });
+    assert_eq!(
+        Instruction::decode(0x6A60),
+        Instruction::Ldr {
+            dr: 5,
+            base: 1,
+            offset: -32
+        }
+    );
+    assert_eq!(
+        Instruction::decode(0x7E1F),
+        Instruction::Str {
+            sr: 7,
+            base: 0,
+            offset: 31
+        }
+    );
+    assert_eq!(
+        Instruction::decode(0xE0FF),
+        Instruction::Lea { dr: 0, offset: 255 }
+    );
+    assert_eq!(
+        Instruction::decode(0xF025),
+        Instruction::Trap { vector: 0x25 }
+    );
+    assert_eq!(Instruction::decode(0x8000), Instruction::Rti);
+    assert_eq!(Instruction::decode(0xD123), Instruction::Reserved(0xD123));
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use common::vm;
+use lc3_vm::io::{BufferIo, IoBackend, ScriptedIo};
+use lc3_vm::VmError;
+
+/// Echo keys in upper case until a newline
+const ECHO: &str = "
+        .ORIG x3000
+LOOP    GETC
+        ADD R1, R0, #-10
+        BRz DONE
+        ADD R0, R0, #-16
+        ADD R0, R0, #-16
+        OUT
+        BRnzp LOOP
+DONE    LEA R0, BYE
+        PUTS
+        HALT
+BYE     .STRINGZ \"!\\n\"
+        .END
+";
+
+#[test]
+fn runs_headless_with_buffers() {
+    let mut vm = vm(ECHO);
+    let io = BufferIo::new(b"vm\n".to_vec());
+    let output = io.output();
+    vm.set_io(io);
+    vm.run().unwrap();
+    assert_eq!(output.lock().unwrap().as_slice(), b"VM!\nHALT detected\n");
+}
+
+#[test]
+fn exhausted_buffer_is_end_of_input() {
+    let mut vm = vm(ECHO);
+    vm.set_io(BufferIo::new(b"ab".to_vec()));
+    assert!(matches!(vm.run(), Err(VmError::Io(_))));
+}
+
+#[test]
+fn scripted_input_arrives_after_idle_polls() {
+    let mut io = ScriptedIo::new()
+        .after(2, b"x".to_vec())
+        .after(0, b"y".to_vec());
+    assert_eq!(io.read_byte().unwrap(), None);
+    assert_eq!(io.read_byte().unwrap(), None);
+    assert_eq!(io.read_byte().unwrap(), Some(b'x'));
+    assert_eq!(io.read_byte().unwrap(), Some(b'y'));
+    // Once the script is exhausted there is no key, and waiting for one fails
+    assert_eq!(io.read_byte().unwrap(), None);
+    assert!(io.read_byte_blocking().is_err());
+
+    let mut vm = vm(ECHO);
+    let io = ScriptedIo::new()
+        .after(100, b"ok".to_vec())
+        .after(50, b"\n".to_vec());
+    let output = io.output();
+    vm.set_io(io);
+    vm.run().unwrap();
+    assert!(output.lock().unwrap().starts_with(b"OK!\n"));
+}
+
+#[test]
+fn exhausted_buffer_has_no_key_to_poll() {
+    let mut io = BufferIo::new(b"a".to_vec());
+    assert_eq!(io.read_byte().unwrap(), Some(b'a'));
+    assert_eq!(io.read_byte().unwrap(), None);
+    assert!(io.read_byte_blocking().is_err());
+}
+
+#[test]
+fn polling_leaves_the_byte_in_place() {
+    let mut io = BufferIo::new(b"a".to_vec());
+    assert!(io.poll().unwrap());
+    assert!(io.poll().unwrap());
+    assert_eq!(io.read_byte().unwrap(), Some(b'a'));
+    assert!(!io.poll().unwrap());
+
+    let mut io = ScriptedIo::new().after(1, b"x".to_vec());
+    assert!(!io.poll().unwrap());
+    assert!(io.poll().unwrap());
+    assert!(io.poll().unwrap());
+    assert_eq!(io.read_byte().unwrap(), Some(b'x'));
+    assert!(!io.poll().unwrap());
+}
+
+#[test]
+fn vm_can_move_to_another_thread() {
+    let mut vm = vm(ECHO);
+    vm.set_io(BufferIo::new(b"\n".to_vec()));
+    std::thread::spawn(move || vm.run().unwrap())
+        .join()
+        .unwrap();
+}
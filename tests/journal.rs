@@ -0,0 +1,106 @@
+mod common;
+
+use lc3_vm::register::LC3CPURegister::{PC, R1};
+use lc3_vm::Vm;
+
+const PROGRAM: &str = "
+        .ORIG x3000
+        AND R1, R1, #0
+LOOP    ADD R1, R1, #1
+        ST R1, COUNT
+        BRnzp LOOP
+COUNT   .FILL #0
+        .END
+";
+
+fn vm(capacity: usize) -> Vm {
+    let mut vm = common::vm(PROGRAM);
+    vm.enable_journal(capacity);
+    vm
+}
+
+#[test]
+fn step_back_restores_registers_memory_and_cycles() {
+    let mut vm = vm(16);
+    for _ in 0..7 {
+        vm.step().unwrap();
+    }
+    assert_eq!((vm.register(R1), vm.memory(0x3004)), (2, 2));
+    for _ in 0..7 {
+        assert!(vm.step_back().is_some());
+    }
+    assert!(vm.step_back().is_none());
+    assert_eq!(vm.register(PC), 0x3000);
+    assert_eq!((vm.register(R1), vm.memory(0x3004)), (0, 0));
+    assert_eq!(vm.cycles(), 0);
+}
+
+#[test]
+fn journal_is_bounded() {
+    let mut vm = vm(3);
+    for _ in 0..10 {
+        vm.step().unwrap();
+    }
+    assert_eq!(vm.journal().unwrap().len(), 3);
+    let mut undone = 0;
+    while vm.step_back().is_some() {
+        undone += 1;
+    }
+    assert_eq!(undone, 3);
+    assert_eq!(vm.cycles(), 7);
+}
+
+#[test]
+fn last_write_finds_the_most_recent_store() {
+    let mut vm = vm(16);
+    for _ in 0..7 {
+        vm.step().unwrap();
+    }
+    let outcome = vm.journal().unwrap().last_write(0x3004).unwrap();
+    assert_eq!((outcome.pc_before, outcome.cycle), (0x3002, 5));
+    assert!(vm.journal().unwrap().last_write(0x3005).is_none());
+}
+
+#[test]
+fn step_back_over_halt_restarts_the_clock() {
+    let mut vm = common::vm(
+        "
+        .ORIG x3000
+        HALT
+        .END
+",
+    );
+    vm.enable_journal(4);
+    let running = vm.memory(0xFFFE);
+    assert_eq!(running & 0x8000, 0x8000);
+    vm.step().unwrap();
+    assert!(vm.is_halted());
+    assert_eq!(vm.memory(0xFFFE) & 0x8000, 0);
+    assert!(vm.step_back().is_some());
+    assert!(!vm.is_halted());
+    assert_eq!(vm.memory(0xFFFE), running);
+    assert_eq!(vm.register(PC), 0x3000);
+}
+
+#[test]
+fn step_back_restores_device_registers() {
+    let mut vm = common::vm(
+        "
+        .ORIG x3000
+        LD R1, ENABLE
+        STI R1, KBSR
+        HALT
+ENABLE  .FILL x4000
+KBSR    .FILL xFE00
+        .END
+",
+    );
+    vm.enable_journal(4);
+    vm.step().unwrap();
+    vm.step().unwrap();
+    assert_eq!(vm.memory(0xFE00), 0x4000);
+    vm.step_back().unwrap();
+    assert_eq!(vm.memory(0xFE00), 0);
+    // The store to the device register did not leak into backing memory
+    assert_eq!(vm.memory(0x3004), 0xFE00);
+}
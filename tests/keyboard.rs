@@ -0,0 +1,87 @@
+mod common;
+
+use common::vm;
+use lc3_vm::io::{BufferIo, ScriptedIo};
+use lc3_vm::register::LC3CPURegister::{PC, R1, R2, R3, R4};
+
+/// Count the polls until a key is ready, read it and check the status register again
+const POLL: &str = "
+        .ORIG x3000
+        AND R1, R1, #0
+POLL    ADD R1, R1, #1
+        LDI R2, KBSR
+        BRzp POLL
+        LDI R3, KBDR
+        LDI R4, KBSR
+        HALT
+KBSR    .FILL xFE00
+KBDR    .FILL xFE02
+        .END
+";
+
+#[test]
+fn polling_sees_no_key_until_one_arrives() {
+    let mut vm = vm(POLL);
+    vm.set_io(ScriptedIo::new().after(25, b"k".to_vec()));
+    vm.run().unwrap();
+    assert_eq!(vm.register(R1), 26);
+    assert_eq!(vm.register(R2), 0x8000);
+    assert_eq!(vm.register(R3), b'k' as u16);
+    // Reading KBDR consumed the key
+    assert_eq!(vm.register(R4), 0);
+}
+
+#[test]
+fn status_polls_do_not_consume_the_key() {
+    let mut vm = vm(POLL);
+    vm.set_io(
+        ScriptedIo::new()
+            .after(0, b"k".to_vec())
+            .after(1000, b"z".to_vec()),
+    );
+    for _ in 0..3 {
+        vm.step().unwrap();
+    }
+    assert_eq!(vm.register(R2), 0x8000);
+    vm.set_register(R2, 0);
+    vm.set_register(PC, 0x3002);
+    for _ in 0..2 {
+        vm.step().unwrap();
+    }
+    assert_eq!(vm.register(R2), 0x8000);
+    vm.run().unwrap();
+    assert_eq!(vm.register(R3), b'k' as u16);
+}
+
+#[test]
+fn data_register_keeps_the_last_key() {
+    let source = "
+        .ORIG x3000
+        LDI R3, KBDR
+        LDI R4, KBDR
+        HALT
+KBDR    .FILL xFE02
+        .END
+";
+    let mut vm = vm(source);
+    vm.set_io(
+        ScriptedIo::new()
+            .after(0, b"a".to_vec())
+            .after(10, b"b".to_vec()),
+    );
+    vm.run().unwrap();
+    assert_eq!(vm.register(R3), b'a' as u16);
+    assert_eq!(vm.register(R4), b'a' as u16);
+}
+
+#[test]
+fn exhausted_console_reports_no_key() {
+    let mut vm = vm(POLL);
+    vm.set_io(BufferIo::new(Vec::new()));
+    for _ in 0..30 {
+        vm.step().unwrap();
+    }
+    assert_eq!(vm.register(R1), 10);
+    assert_eq!(vm.register(R2), 0);
+    assert_eq!(vm.register(PC), 0x3003);
+}
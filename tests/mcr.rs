@@ -0,0 +1,75 @@
+mod common;
+
+use common::vm;
+use lc3_vm::register::LC3CPURegister::{PC, R1};
+use lc3_vm::{StopReason, VmError};
+
+#[test]
+fn clearing_the_clock_enable_bit_halts() {
+    // HALT the way the LC-3 operating system implements it
+    let mut vm = vm("
+        .ORIG x3000
+        ADD R1, R1, #1
+        LDI R0, MCR
+        LD R2, MASK
+        AND R0, R0, R2
+        STI R0, MCR
+        ADD R1, R1, #1
+MCR     .FILL xFFFE
+MASK    .FILL x7FFF
+        .END
+");
+    assert_eq!(vm.memory(0xFFFE), 0x8000);
+    assert_eq!(vm.run().unwrap(), StopReason::Halted);
+    assert!(vm.is_halted());
+    assert_eq!(vm.register(R1), 1);
+    assert_eq!(vm.register(PC), 0x3005);
+    assert_eq!(vm.memory(0xFFFE), 0x0000);
+    assert!(matches!(vm.step(), Err(VmError::Halted)));
+}
+
+#[test]
+fn writes_keeping_the_clock_enabled_do_not_halt() {
+    let mut vm = vm("
+        .ORIG x3000
+        LD R0, ENABLED
+        STI R0, MCR
+        ADD R1, R1, #1
+        HALT
+MCR     .FILL xFFFE
+ENABLED .FILL xFFFF
+        .END
+");
+    vm.run().unwrap();
+    assert_eq!(vm.register(R1), 1);
+}
+
+#[test]
+fn trap_halt_stops_the_clock() {
+    let mut vm = vm("
+        .ORIG x3000
+        HALT
+        .END
+");
+    let outcome = vm.step().unwrap();
+    assert!(outcome.halted);
+    assert_eq!(vm.memory(0xFFFE) & 0x8000, 0);
+}
+
+#[test]
+fn loading_an_image_over_the_io_page_leaves_the_devices_alone() {
+    let mut vm = vm("
+        .ORIG x3000
+        ADD R1, R1, #1
+        HALT
+        .END
+");
+    // Words for MCR and beyond, writing them through the bus would stop the clock
+    vm.load_from(&[0xFF, 0xFE, 0x00, 0x00, 0x00, 0x00][..])
+        .unwrap();
+    assert_eq!(vm.memory(0xFFFE), 0x8000);
+    vm.set_register(PC, 0x3000);
+    vm.step().unwrap();
+    assert!(!vm.is_halted());
+    assert_eq!(vm.register(R1), 1);
+}
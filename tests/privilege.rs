@@ -0,0 +1,111 @@
+mod common;
+
+use common::vm;
+use lc3_vm::io::ScriptedIo;
+use lc3_vm::register::LC3CPURegister::{COND, PC, PSR, R0, R1, R6, SSP, USP};
+use lc3_vm::VmError;
+
+/// Supervisor code dropping to user mode: push the user PSR and PC, then return from
+/// "interrupt" into the user program
+const ENTER_USER: &str = "
+        .ORIG x3000
+        LD R6, SSTACK
+        LD R0, UPSR
+        ADD R6, R6, #-1
+        STR R0, R6, #0
+        LEA R0, USER
+        ADD R6, R6, #-1
+        STR R0, R6, #0
+        RTI
+USER    LD R1, SYSTEM
+        LDI R1, SYSTEM
+        HALT
+SSTACK  .FILL x3000
+UPSR    .FILL x8002
+SYSTEM  .FILL x2000
+        .END
+";
+
+#[test]
+fn machine_starts_in_supervisor_mode() {
+    let mut vm = vm("
+        .ORIG x3000
+        LDI R0, PSR
+        ADD R1, R1, #-1
+        HALT
+PSR     .FILL xFFFC
+        .END
+");
+    assert_eq!(vm.register(PSR), 0x0002);
+    assert_eq!(vm.register(SSP), 0x3000);
+    vm.step().unwrap();
+    assert_eq!(vm.register(R0), 0x0002);
+    // The condition codes live in PSR and COND alike
+    vm.step().unwrap();
+    assert_eq!(vm.register(COND), 0b100);
+    assert_eq!(vm.register(PSR), 0x0004);
+    assert_eq!(vm.memory(0xFFFC), 0x0004);
+}
+
+#[test]
+fn rti_switches_to_user_mode_and_the_user_stack() {
+    let mut vm = vm(ENTER_USER);
+    // RTI reads the user stack pointer from the saved USP register, set it from the host
+    vm.set_register(USP, 0x4000);
+    while vm.register(PC) != 0x3008 {
+        vm.step().unwrap();
+    }
+    assert_eq!(vm.register(PSR), 0x8002);
+    assert_eq!(vm.register(R6), 0x4000);
+    assert_eq!(vm.register(SSP), 0x3000);
+    // User code may read user space, the address it loads points into system space
+    vm.step().unwrap();
+    assert_eq!(vm.register(R1), 0x2000);
+    assert!(matches!(vm.step(), Err(VmError::AccessViolation(0x2000))));
+}
+
+#[test]
+fn user_mode_cannot_touch_devices_or_return_from_interrupt() {
+    let mut vm = vm("
+        .ORIG x3000
+        STI R0, KBSR
+        RTI
+KBSR    .FILL xFE00
+        .END
+");
+    vm.set_register(PSR, 0x8002);
+    assert!(matches!(vm.step(), Err(VmError::AccessViolation(0xFE00))));
+    vm.set_register(PC, 0x3001);
+    assert!(matches!(
+        vm.step(),
+        Err(VmError::PrivilegeViolation(0x8000))
+    ));
+}
+
+#[test]
+fn keyboard_interrupt_runs_the_service_routine_on_the_supervisor_stack() {
+    let mut vm = vm("
+        .ORIG x3000
+WAIT    ADD R1, R1, #0
+        BRz WAIT
+        HALT
+ISR     LDI R1, KBDR
+        RTI
+KBDR    .FILL xFE02
+        .END
+");
+    vm.set_io(ScriptedIo::new().after(3, b"a").after(1000, b"z"));
+    vm.set_memory(0x0180, 0x3003);
+    vm.set_memory(0xFE00, 0x4000);
+    vm.set_register(PSR, 0x8002);
+    vm.set_register(R6, 0x4000);
+    vm.run().unwrap();
+    assert_eq!(vm.register(R1), b'a' as u16);
+    // The interrupted PSR and PC were pushed on the supervisor stack
+    assert_eq!(vm.memory(0x2FFF), 0x8002);
+    assert!((0x3000..=0x3001).contains(&vm.memory(0x2FFE)));
+    // Back in user mode on the user stack
+    assert_eq!(vm.register(PSR) & 0x8000, 0x8000);
+    assert_eq!(vm.register(R6), 0x4000);
+    assert_eq!(vm.register(SSP), 0x3000);
+}
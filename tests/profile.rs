@@ -0,0 +1,78 @@
+mod common;
+
+use lc3_vm::asm::assemble;
+use lc3_vm::disasm::Labels;
+use lc3_vm::instruction::LC3Instruction;
+use lc3_vm::profile::Profiler;
+
+const PROGRAM: &str = "
+        .ORIG x3000
+        LD R1, N
+OUTER   JSR WORK
+        ADD R1, R1, #-1
+        BRp OUTER
+        HALT
+WORK    ST R7, SAVE
+        AND R2, R2, #0
+        ADD R2, R2, #5
+INNER   JSR TINY
+        ADD R2, R2, #-1
+        BRp INNER
+        LD R7, SAVE
+        RET
+TINY    ADD R3, R3, #1
+        RET
+N       .FILL #4
+SAVE    .FILL #0
+        .END
+";
+
+fn profile() -> (Profiler, Labels) {
+    let assembly = assemble(PROGRAM).unwrap();
+    let mut vm = common::vm(PROGRAM);
+    let mut profiler = Profiler::new();
+    loop {
+        let outcome = vm.step().unwrap();
+        profiler.record(&outcome);
+        if outcome.halted {
+            break;
+        }
+    }
+    let labels = assembly
+        .symbols
+        .into_iter()
+        .map(|(name, address)| (address, name))
+        .collect();
+    (profiler, labels)
+}
+
+#[test]
+fn counts_per_address_and_opcode() {
+    let (profiler, _) = profile();
+    assert_eq!(profiler.total(), 134);
+    assert_eq!(profiler.count(0x3000), 1);
+    assert_eq!(profiler.count(0x300D), 20);
+    assert_eq!(profiler.opcode_count(LC3Instruction::JSR), 24);
+    assert_eq!(profiler.opcode_count(LC3Instruction::JMP), 24);
+}
+
+#[test]
+fn folded_stacks_follow_jsr_and_ret() {
+    let (profiler, labels) = profile();
+    assert_eq!(
+        profiler.folded(&labels),
+        "x3000 14\nx3000;WORK 80\nx3000;WORK;TINY 40\n"
+    );
+}
+
+#[test]
+fn report_lists_the_hottest_address_first() {
+    let (profiler, labels) = profile();
+    let report = profiler.report(&labels);
+    let hot_spots = report.split("hot spots:\n").nth(1).unwrap();
+    assert!(hot_spots
+        .lines()
+        .next()
+        .unwrap()
+        .contains("INNER   JSR TINY"));
+}
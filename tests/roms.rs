@@ -0,0 +1,30 @@
+//! The bundled images must start executing at their origin, not one word past it.
+use lc3_vm::register::LC3CPURegister::PC;
+use lc3_vm::Vm;
+
+fn load(name: &str) -> Vm {
+    let mut vm = Vm::new();
+    vm.load(format!("{}/src/roms/{}", env!("CARGO_MANIFEST_DIR"), name))
+        .unwrap();
+    vm
+}
+
+fn assert_starts_at_origin(name: &str, origin: u16) {
+    let mut vm = load(name);
+    assert_eq!(vm.register(PC), origin);
+
+    let first_word = vm.memory(origin);
+    let outcome = vm.step().unwrap();
+    assert_eq!(outcome.pc_before, origin);
+    assert_eq!(outcome.instruction, first_word);
+}
+
+#[test]
+fn game_2048_starts_at_entry_point() {
+    assert_starts_at_origin("2048.obj", 0x3000);
+}
+
+#[test]
+fn rogue_starts_at_entry_point() {
+    assert_starts_at_origin("rogue.obj", 0x3000);
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use lc3_vm::asm::assemble;
+use lc3_vm::register::LC3CPURegister::{PC, R1};
+use lc3_vm::snapshot::Snapshot;
+use lc3_vm::{Vm, VmError};
+use std::env;
+use std::fs::{self, File};
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+const PROGRAM: &str = "
+        .ORIG x3000
+        AND R1, R1, #0
+LOOP    ADD R1, R1, #1
+        ST R1, COUNT
+        ADD R2, R1, #-8
+        BRn LOOP
+        HALT
+COUNT   .FILL #0
+        .END
+";
+
+fn vm() -> Vm {
+    common::vm(PROGRAM)
+}
+
+#[test]
+fn restored_machine_continues_where_the_snapshot_was_taken() {
+    let mut vm = vm();
+    for _ in 0..10 {
+        vm.step().unwrap();
+    }
+    let mut file = Vec::new();
+    vm.snapshot().write(&mut file).unwrap();
+
+    let mut resumed = Vm::new();
+    resumed
+        .restore(&Snapshot::read(file.as_slice()).unwrap())
+        .unwrap();
+    assert_eq!(resumed.snapshot(), vm.snapshot());
+    assert_eq!(resumed.cycles(), 10);
+
+    vm.run().unwrap();
+    resumed.run().unwrap();
+    assert!(resumed.is_halted());
+    assert_eq!(resumed.register(R1), 8);
+    assert_eq!(resumed.memory(0x3006), 8);
+    assert_eq!(resumed.snapshot(), vm.snapshot());
+}
+
+#[test]
+fn halted_flag_is_saved() {
+    let mut vm = vm();
+    vm.run().unwrap();
+    let mut file = Vec::new();
+    vm.snapshot().write(&mut file).unwrap();
+    let mut resumed = Vm::new();
+    resumed
+        .restore(&Snapshot::read(file.as_slice()).unwrap())
+        .unwrap();
+    assert!(resumed.is_halted());
+    assert_eq!(resumed.register(PC), vm.register(PC));
+    assert!(matches!(resumed.step(), Err(VmError::Halted)));
+}
+
+#[test]
+fn rejects_files_that_are_not_snapshots() {
+    let mut file = Vec::new();
+    vm().snapshot().write(&mut file).unwrap();
+    file[4] = 0xFF;
+    assert!(matches!(
+        Snapshot::read(file.as_slice()),
+        Err(VmError::InvalidSnapshot(_))
+    ));
+    assert!(matches!(
+        Snapshot::read(&b"LC3T\x01"[..]),
+        Err(VmError::InvalidSnapshot(_))
+    ));
+}
+
+#[test]
+fn device_state_lengths_are_32_bits() {
+    let mut file = Vec::new();
+    vm().snapshot().write(&mut file).unwrap();
+    assert_eq!(&file[4..6], &[0, 1]);
+    // The machine control register is the last device: a 32-bit length and its value
+    assert_eq!(&file[file.len() - 6..], &[0, 0, 0, 2, 0x80, 0x00]);
+}
+
+#[test]
+fn rejected_snapshots_leave_the_machine_unchanged() {
+    let mut vm = vm();
+    vm.step().unwrap();
+    let before = vm.snapshot();
+    let mut snapshot = before.clone();
+    snapshot.registers[PC as usize] = 0x4000;
+    snapshot.memory[0x3000] = 0;
+    // The keyboard state is valid and enables interrupts, the display state is not
+    snapshot.devices[0] = vec![1, 0, 0x62];
+    snapshot.devices[1] = vec![0, 0, 0];
+    assert!(matches!(
+        vm.restore(&snapshot),
+        Err(VmError::InvalidSnapshot(_))
+    ));
+    assert_eq!(vm.snapshot(), before);
+    assert_eq!(vm.memory(0xFE00), 0x0000);
+}
+
+/// Run the binary on `image` with extra arguments
+fn run(image: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lc3-vm"))
+        .arg("--no-raw")
+        .args(args)
+        .arg(image)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn programs_saved_before_halting_resume() {
+    let image = env::temp_dir().join(format!("lc3-vm-snapshot-{}.obj", std::process::id()));
+    assemble(
+        "
+        .ORIG x3000
+        LD R0, CHAR
+        LD R2, END
+LOOP    OUT
+        ADD R0, R0, #1
+        ADD R1, R0, R2
+        BRn LOOP
+        HALT
+CHAR    .FILL x61
+END     .FILL #-100
+        .END
+",
+    )
+    .unwrap()
+    .write_obj(File::create(&image).unwrap())
+    .unwrap();
+    let state = image.with_extension("state");
+    let state_arg = state.to_str().unwrap();
+
+    let output = run(&image, &["--save-state", state_arg, "--save-state-at", "4"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"a");
+    let output = run(&image, &["--load-state", state_arg]);
+    assert!(output.status.success());
+    assert!(output.stdout.starts_with(b"bc"));
+
+    // Once halted there is nothing left to resume
+    fs::remove_file(&state).unwrap();
+    let output = run(&image, &["--save-state", state_arg]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("cannot be resumed"), "{}", stderr);
+    assert!(!state.exists());
+    fs::remove_file(&image).unwrap();
+}
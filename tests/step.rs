@@ -0,0 +1,71 @@
+mod common;
+
+use common::vm;
+use lc3_vm::instruction::LC3Instruction;
+use lc3_vm::register::LC3CPURegister::{COND, PC, R1};
+use lc3_vm::{MemoryWrite, RegisterWrite};
+
+const PROGRAM: &str = "
+        .ORIG x3000
+        ADD R1, R1, #5
+        ST R1, X
+        BRp SKIP
+        ADD R1, R1, #1
+SKIP    HALT
+X       .FILL #0
+        .END
+";
+
+#[test]
+fn step_reports_the_executed_instruction_and_register_writes() {
+    let mut vm = vm(PROGRAM);
+    let outcome = vm.step().unwrap();
+    assert_eq!(outcome.cycle, 0);
+    assert_eq!(outcome.instruction, 0x1265);
+    assert_eq!(outcome.opcode, LC3Instruction::ADD);
+    assert_eq!(outcome.pc_before, 0x3000);
+    assert_eq!(outcome.pc_after, 0x3001);
+    assert!(outcome.registers_written.contains(&RegisterWrite {
+        register: R1 as usize,
+        old: 0,
+        new: 5,
+    }));
+    assert!(outcome.registers_written.contains(&RegisterWrite {
+        register: COND as usize,
+        old: 0b010,
+        new: 0b001,
+    }));
+    assert!(outcome.memory_written.is_empty());
+    assert_eq!(outcome.trap, None);
+    assert!(!outcome.halted);
+}
+
+#[test]
+fn step_reports_memory_writes_and_taken_branches() {
+    let mut vm = vm(PROGRAM);
+    vm.step().unwrap();
+    let store = vm.step().unwrap();
+    assert_eq!(store.cycle, 1);
+    assert_eq!(store.opcode, LC3Instruction::ST);
+    // A store only moves PC
+    assert_eq!(
+        store.registers_written,
+        vec![RegisterWrite {
+            register: PC as usize,
+            old: 0x3001,
+            new: 0x3002,
+        }]
+    );
+    assert_eq!(
+        store.memory_written,
+        vec![MemoryWrite {
+            address: 0x3005,
+            old: 0,
+            new: 5,
+        }]
+    );
+    let branch = vm.step().unwrap();
+    assert_eq!(branch.opcode, LC3Instruction::BR);
+    assert_eq!(branch.pc_before, 0x3002);
+    assert_eq!(branch.pc_after, 0x3004);
+}
@@ -0,0 +1,83 @@
+//! Raw mode on a pseudo-terminal standing in for the user's terminal.
+#![cfg(unix)]
+
+use lc3_vm::terminal::RawMode;
+use std::fs::File;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::process::{Command, Stdio};
+use std::{env, ptr};
+
+/// Open a pseudo-terminal, returns the controller and the terminal side
+fn openpty() -> (OwnedFd, OwnedFd) {
+    let (mut controller, mut terminal) = (0, 0);
+    // SAFETY: openpty writes two new file descriptors, which are owned from here on
+    unsafe {
+        let result = libc::openpty(
+            &mut controller,
+            &mut terminal,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+        );
+        assert_eq!(result, 0, "{}", std::io::Error::last_os_error());
+        (
+            OwnedFd::from_raw_fd(controller),
+            OwnedFd::from_raw_fd(terminal),
+        )
+    }
+}
+
+fn local_flags(fd: libc::c_int) -> libc::tcflag_t {
+    // SAFETY: tcgetattr only writes the termios value we own
+    unsafe {
+        let mut termios: libc::termios = std::mem::zeroed();
+        assert_eq!(libc::tcgetattr(fd, &mut termios), 0);
+        termios.c_lflag
+    }
+}
+
+#[test]
+fn raw_mode_is_restored_on_drop() {
+    let (_controller, terminal) = openpty();
+    // Stand the pseudo-terminal in for stdin, no other test in this binary reads stdin
+    // SAFETY: dup and dup2 only duplicate descriptors, stdin is put back below
+    let stdin = unsafe { libc::dup(libc::STDIN_FILENO) };
+    assert!(stdin >= 0);
+    unsafe { libc::dup2(terminal.as_raw_fd(), libc::STDIN_FILENO) };
+
+    let cooked = local_flags(libc::STDIN_FILENO);
+    assert_ne!(cooked & libc::ICANON, 0);
+    let raw_mode = RawMode::enable().unwrap();
+    assert!(raw_mode.is_some());
+    assert_eq!(
+        local_flags(libc::STDIN_FILENO) & (libc::ICANON | libc::ECHO),
+        0
+    );
+    drop(raw_mode);
+    let restored = local_flags(libc::STDIN_FILENO);
+
+    unsafe {
+        libc::dup2(stdin, libc::STDIN_FILENO);
+        libc::close(stdin);
+    }
+    assert_eq!(restored, cooked);
+}
+
+#[test]
+fn failing_setup_leaves_the_terminal_alone() {
+    let (_controller, terminal) = openpty();
+    let cooked = local_flags(terminal.as_raw_fd());
+    let image = env::temp_dir().join(format!("lc3-vm-terminal-{}.obj", std::process::id()));
+    std::fs::write(&image, [0x30, 0x00, 0xF0, 0x25]).unwrap();
+    // The trace cannot be created, the error is reported before raw mode would be entered
+    let output = Command::new(env!("CARGO_BIN_EXE_lc3-vm"))
+        .arg("--trace")
+        .arg(image.with_extension("missing").join("trace.jsonl"))
+        .arg(&image)
+        .stdin(Stdio::from(File::from(terminal.try_clone().unwrap())))
+        .output()
+        .unwrap();
+    std::fs::remove_file(&image).unwrap();
+    assert!(!output.status.success());
+    assert_eq!(local_flags(terminal.as_raw_fd()), cooked);
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use lc3_vm::asm::assemble;
+use lc3_vm::register::LC3CPURegister::COND;
+use lc3_vm::trace::{first_difference, read_trace, TraceFormat, TraceRecord, Tracer};
+use std::env;
+use std::fs::File;
+use std::process::{Command, Stdio};
+
+const PROGRAM: &str = "
+        .ORIG x3000
+        AND R1, R1, #0
+        ADD R1, R1, #3
+LOOP    ADD R1, R1, #-1
+        ST R1, COUNT
+        BRp LOOP
+        HALT
+COUNT   .FILL #0
+        .END
+";
+
+/// Run the program to HALT and return its trace in `format`
+fn trace(format: TraceFormat) -> Vec<u8> {
+    let mut vm = common::vm(PROGRAM);
+    let mut tracer = Tracer::new(Vec::new(), format).unwrap();
+    loop {
+        let outcome = vm.step().unwrap();
+        tracer
+            .record(&TraceRecord::new(&outcome, vm.register(COND)))
+            .unwrap();
+        if outcome.halted {
+            return tracer.finish().unwrap();
+        }
+    }
+}
+
+#[test]
+fn jsonl_has_one_record_per_instruction() {
+    let text = String::from_utf8(trace(TraceFormat::Jsonl)).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 12);
+    assert_eq!(
+        lines[3],
+        "{\"cycle\":3,\"pc\":12291,\"word\":12802,\"asm\":\"ST R1, x3006\",\"cond\":\"p\",\
+         \"regs\":[{\"reg\":\"PC\",\"old\":12291,\"new\":12292}],\
+         \"mem\":[{\"addr\":12294,\"old\":0,\"new\":2}]}"
+    );
+}
+
+#[test]
+fn binary_traces_read_back_the_records() {
+    let records = read_trace(trace(TraceFormat::Binary).as_slice()).unwrap();
+    assert_eq!(records.len(), 12);
+    assert_eq!(
+        records[3].to_string(),
+        "#3 x3003  x3202  ST R1, x3006         p PC=x3003->x3004 [x3006]=x0000->x0002"
+    );
+}
+
+#[test]
+fn jsonl_traces_are_not_read_back() {
+    for text in [trace(TraceFormat::Jsonl), Vec::new()] {
+        let error = read_trace(text.as_slice()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "not a binary trace, record it with --trace-format binary"
+        );
+    }
+}
+
+#[test]
+fn first_difference_finds_the_divergence() {
+    let a = read_trace(trace(TraceFormat::Binary).as_slice()).unwrap();
+    let mut b = a.clone();
+    b[3].memory[0].new = 7;
+    assert_eq!(first_difference(&a, &b), Some(3));
+    assert_eq!(first_difference(&a, &a[..6]), Some(6));
+}
+
+#[test]
+fn invalid_condition_codes_round_trip() {
+    let mut record = read_trace(trace(TraceFormat::Binary).as_slice()).unwrap()[0].clone();
+    record.cond = 0b011;
+    let mut tracer = Tracer::new(Vec::new(), TraceFormat::Jsonl).unwrap();
+    tracer.record(&record).unwrap();
+    let text = String::from_utf8(tracer.finish().unwrap()).unwrap();
+    assert!(text.contains("\"cond\":\"x0003\""));
+    let mut tracer = Tracer::new(Vec::new(), TraceFormat::Binary).unwrap();
+    tracer.record(&record).unwrap();
+    let bytes = tracer.finish().unwrap();
+    assert_eq!(read_trace(bytes.as_slice()).unwrap(), vec![record]);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn failing_trace_writes_keep_the_profile() {
+    // Long enough for the trace to overflow the write buffer well before HALT
+    let program = "
+        .ORIG x3000
+        LD R1, N
+LOOP    ADD R1, R1, #-1
+        BRp LOOP
+        HALT
+N       .FILL #1000
+        .END
+";
+    let path = env::temp_dir().join(format!("lc3-vm-trace-{}.obj", std::process::id()));
+    assemble(program)
+        .unwrap()
+        .write_obj(File::create(&path).unwrap())
+        .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_lc3-vm"))
+        .args(["--no-raw", "--profile", "--trace", "/dev/full"])
+        .arg(&path)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    // The profile covers the instructions run up to the failed write
+    let executed: u32 = stderr.split_whitespace().next().unwrap().parse().unwrap();
+    assert!(executed > 0 && executed < 2002, "{}", stderr);
+    assert!(stderr.contains("No space left on device"), "{}", stderr);
+}
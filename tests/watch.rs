@@ -0,0 +1,52 @@
+mod common;
+
+use lc3_vm::watch::{WatchKind, Watchpoint};
+use lc3_vm::{StopReason, Vm};
+
+const PROGRAM: &str = "
+        .ORIG x3000
+        AND R1, R1, #0
+LOOP    ADD R1, R1, #1
+        ST R1, COUNT
+        LD R2, COUNT
+        BRnzp LOOP
+COUNT   .FILL #0
+        .END
+";
+
+fn vm() -> Vm {
+    common::vm(PROGRAM)
+}
+
+#[test]
+fn write_watchpoint_reports_old_and_new_value() {
+    let mut vm = vm();
+    vm.add_watchpoint(Watchpoint::new(0x3005, WatchKind::Write));
+    let StopReason::Watchpoint(hits) = vm.run().unwrap() else {
+        panic!("expected a watchpoint");
+    };
+    assert_eq!(hits.len(), 1);
+    assert_eq!((hits[0].pc, hits[0].address), (0x3002, 0x3005));
+    assert_eq!((hits[0].old, hits[0].new), (0, 1));
+}
+
+#[test]
+fn read_watchpoint_with_value_condition() {
+    let mut vm = vm();
+    vm.add_watchpoint(Watchpoint::range(0x3004, 0x3006, WatchKind::Read).with_value(3));
+    let StopReason::Watchpoint(hits) = vm.run().unwrap() else {
+        panic!("expected a watchpoint");
+    };
+    assert_eq!(hits[0].pc, 0x3003);
+    assert_eq!(hits[0].kind, WatchKind::Read);
+    assert_eq!(hits[0].new, 3);
+}
+
+#[test]
+fn access_watchpoint_ignores_instruction_fetch() {
+    let mut vm = vm();
+    vm.add_watchpoint(Watchpoint::range(0x3000, 0x3004, WatchKind::Access));
+    for _ in 0..20 {
+        assert!(vm.step().unwrap().watch_hits.is_empty());
+    }
+}
@@ -0,0 +1,156 @@
+//! Regression tests for two's-complement wrapping in the execution engine. Negative offsets and
+//! values around 0xFFFF used to overflow the plain `u16` additions and panic in debug builds.
+use lc3_vm::io::BufferIo;
+use lc3_vm::register::LC3CPURegister::{self, *};
+use lc3_vm::register::LC3ConditionalFlags;
+use lc3_vm::Vm;
+
+fn add_imm(dr: u16, sr1: u16, imm5: i16) -> u16 {
+    0x1000 | dr << 9 | sr1 << 6 | 1 << 5 | (imm5 as u16 & 0x1F)
+}
+
+fn add_reg(dr: u16, sr1: u16, sr2: u16) -> u16 {
+    0x1000 | dr << 9 | sr1 << 6 | sr2
+}
+
+fn br(nzp: u16, offset9: i16) -> u16 {
+    nzp << 9 | (offset9 as u16 & 0x1FF)
+}
+
+fn ld(dr: u16, offset9: i16) -> u16 {
+    0x2000 | dr << 9 | (offset9 as u16 & 0x1FF)
+}
+
+fn lea(dr: u16, offset9: i16) -> u16 {
+    0xE000 | dr << 9 | (offset9 as u16 & 0x1FF)
+}
+
+fn jsr(offset11: i16) -> u16 {
+    0x4800 | (offset11 as u16 & 0x7FF)
+}
+
+fn ldr(dr: u16, base: u16, offset6: i16) -> u16 {
+    0x6000 | dr << 9 | base << 6 | (offset6 as u16 & 0x3F)
+}
+
+fn str(sr: u16, base: u16, offset6: i16) -> u16 {
+    0x7000 | sr << 9 | base << 6 | (offset6 as u16 & 0x3F)
+}
+
+/// Place `words` at `address` and point the machine at the first one
+fn vm_at(address: u16, words: &[u16]) -> Vm {
+    let mut vm = Vm::new();
+    for (i, word) in words.iter().enumerate() {
+        vm.set_memory(address.wrapping_add(i as u16), *word);
+    }
+    vm.set_register(PC, address);
+    vm
+}
+
+/// Value of PC seen by PC-relative addressing while executing the instruction at `address`
+fn pc_during(address: u16) -> u16 {
+    address.wrapping_add(1)
+}
+
+fn cond(vm: &Vm) -> u16 {
+    vm.register(LC3CPURegister::COND)
+}
+
+#[test]
+fn add_negative_immediate() {
+    let mut vm = vm_at(0x3000, &[add_imm(2, 1, -3)]);
+    vm.set_register(R1, 5);
+    vm.step().unwrap();
+    assert_eq!(vm.register(R2), 2);
+    assert_eq!(cond(&vm), LC3ConditionalFlags::POS as u16);
+}
+
+#[test]
+fn add_immediate_wraps_at_0xffff() {
+    let mut vm = vm_at(0x3000, &[add_imm(1, 1, 1)]);
+    vm.set_register(R1, 0xFFFF);
+    vm.step().unwrap();
+    assert_eq!(vm.register(R1), 0);
+    assert_eq!(cond(&vm), LC3ConditionalFlags::ZRO as u16);
+}
+
+#[test]
+fn add_register_wraps() {
+    let mut vm = vm_at(0x3000, &[add_reg(0, 1, 2)]);
+    vm.set_register(R1, 0x8000);
+    vm.set_register(R2, 0x8001);
+    vm.step().unwrap();
+    assert_eq!(vm.register(R0), 1);
+    assert_eq!(cond(&vm), LC3ConditionalFlags::POS as u16);
+}
+
+#[test]
+fn backward_branch() {
+    let mut vm = vm_at(0x3000, &[add_imm(0, 0, 0), br(0b111, -2)]);
+    vm.step().unwrap();
+    let outcome = vm.step().unwrap();
+    assert_eq!(outcome.pc_after, pc_during(0x3001).wrapping_sub(2));
+}
+
+#[test]
+fn load_and_lea_with_negative_offset() {
+    let mut vm = vm_at(0x3001, &[ld(0, -2), lea(1, -3)]);
+    vm.set_memory(pc_during(0x3001).wrapping_sub(2), 0x1234);
+    vm.step().unwrap();
+    assert_eq!(vm.register(R0), 0x1234);
+    vm.step().unwrap();
+    assert_eq!(vm.register(R1), pc_during(0x3002).wrapping_sub(3));
+}
+
+#[test]
+fn jsr_backward() {
+    let mut vm = vm_at(0x3010, &[jsr(-0x11)]);
+    vm.step().unwrap();
+    assert_eq!(vm.register(R7), pc_during(0x3010));
+    assert_eq!(vm.register(PC), pc_during(0x3010).wrapping_sub(0x11));
+}
+
+#[test]
+fn base_offset_wraps_below_zero() {
+    let mut vm = vm_at(0x3000, &[str(0, 1, -2), ldr(2, 1, -2)]);
+    vm.set_register(R0, 0xBEEF);
+    vm.set_register(R1, 0x0001);
+    vm.step().unwrap();
+    assert_eq!(vm.memory(0xFFFF), 0xBEEF);
+    vm.step().unwrap();
+    assert_eq!(vm.register(R2), 0xBEEF);
+    assert_eq!(cond(&vm), LC3ConditionalFlags::NEG as u16);
+}
+
+#[test]
+fn pc_relative_wraps_past_0xffff() {
+    // 0xFFFE is the machine control register, writing an instruction there would stop the clock
+    let mut vm = vm_at(0xFFFD, &[ld(0, 3)]);
+    vm.set_memory(pc_during(0xFFFD).wrapping_add(3), 0x0042);
+    vm.step().unwrap();
+    assert_eq!(vm.register(R0), 0x0042);
+}
+
+#[test]
+fn program_counter_wraps_to_zero() {
+    let mut vm = vm_at(0xFFFF, &[add_imm(0, 0, 1), add_imm(0, 0, 1)]);
+    vm.step().unwrap();
+    let outcome = vm.step().unwrap();
+    assert_eq!(outcome.pc_before, 0x0000);
+    assert_eq!(vm.register(R0), 2);
+}
+
+#[test]
+fn puts_walks_across_0xffff() {
+    // PUTS with the string starting at the last word of memory
+    let mut vm = vm_at(0x3000, &[0xF022]);
+    let io = BufferIo::new(Vec::new());
+    let output = io.output();
+    vm.set_io(io);
+    vm.set_memory(0xFFFF, b'A' as u16);
+    vm.set_memory(0x0000, b'B' as u16);
+    vm.set_memory(0x0001, 0);
+    vm.set_register(R0, 0xFFFF);
+    vm.step().unwrap();
+    assert_eq!(output.lock().unwrap().as_slice(), b"AB");
+}